@@ -3,17 +3,19 @@
 use block_ciphers::aes::SBox;
 use std::fmt;
 use std::ops::{Add, AddAssign, Mul, MulAssign};
+use std::sync::OnceLock;
 
 
 /// The AES algorithm manipulates bytes, which are interpreted as elements of the
 /// finite field GF(2^8) using a polynomial representation. For example, the
 /// number 0b01100011 denotes the finite field element x^6 + x^5 + x + 1.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Eq, PartialEq)]
 pub struct GFByte {
     byte: u8,
 }
 
 /// The addition operator in the finite field maps to bitwise byte XOR
+#[allow(clippy::suspicious_arithmetic_impl)]
 impl Add for GFByte {
     type Output = Self;
 
@@ -24,12 +26,16 @@ impl Add for GFByte {
     }
 }
 //
+#[allow(clippy::suspicious_op_assign_impl)]
 impl AddAssign for GFByte {
     fn add_assign(&mut self, rhs: Self) {
         self.byte ^= rhs.byte;
     }
 }
 
+// Irreducible polynomial m(x) used by AES for reduction, see the Mul impl
+pub const AES_POLYNOMIAL: u16 = 0x011b;
+//
 /// The multiplication operator corresponds with the multiplication of
 /// polynomials modulo an irreducible polynomial, which for the AES algorithm is
 /// m(x) = x^8 + x^4 + x^3 + x + 1 (mapping to the 16-bit number 0x011b)
@@ -37,17 +43,71 @@ impl Mul for GFByte {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self {
+        self.mul_mod(rhs, AES_POLYNOMIAL)
+    }
+}
+//
+impl MulAssign for GFByte {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs
+    }
+}
+
+/// Bytes are displayed as in the AES standard
+impl fmt::Display for GFByte {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02x}", self.byte)
+    }
+}
+//
+/// Debug output is in hex as well, which is more readable than decimal
+impl fmt::Debug for GFByte {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GFByte({:#04x})", self.byte)
+    }
+}
+
+/// Explicit conversions to and from bytes are provided
+impl From<u8> for GFByte {
+    fn from(byte: u8) -> Self {
+        Self {
+            byte,
+        }
+    }
+}
+//
+impl From<GFByte> for u8 {
+    fn from(gf_byte: GFByte) -> Self {
+        gf_byte.byte
+    }
+}
+
+/// In addition, we provide some extra specific crypto primitives on bytes
+impl GFByte {
+    /// Applying an S-box to a GFByte applies it to the inner byte
+    pub fn apply_s_box(&mut self, sb: &SBox) {
+        self.byte = sb[self.byte as usize];
+    }
+
+    /// Multiplication may also be carried out modulo another polynomial of
+    /// degree 8, to study what happens when it is not irreducible
+    pub fn mul_mod(self, rhs: Self, reduction_poly: u16) -> Self {
+        assert_eq!(reduction_poly >> 8, 1,
+                   "Reduction polynomial must have degree 8");
+
         // Let's extract the two input bytes
         let (b1, b2) = (self.byte, rhs.byte);
 
         // Multiplication by x (0b10) can be implemented as follows:
         //      - Check the high-order bit
         //      - Apply a left bitshift to the byte
-        //      - Apply modulo as conditional subtraction (bitwise XOR) of 0x1b
+        //      - Apply modulo as conditional subtraction (bitwise XOR) of the
+        //        low-order byte of the reduction polynomial (0x1b for AES)
+        let low_order_poly = reduction_poly as u8;
         let mul_x = |b: u8| -> u8 {
             let high_order_bit = b >> 7;
             let shifted_b = b << 1;
-            let conditional_sub = high_order_bit * 0x1b;
+            let conditional_sub = high_order_bit * low_order_poly;
             shifted_b ^ conditional_sub
         };
 
@@ -58,9 +118,9 @@ impl Mul for GFByte {
         let mut multiplier = b1;
         for _ in 0..8 {
             let low_order_bit = remainder & 1;
-            accumulator = accumulator ^ (multiplier * low_order_bit);
+            accumulator ^= multiplier * low_order_bit;
             multiplier = mul_x(multiplier);
-            remainder = remainder >> 1;
+            remainder >>= 1;
         }
 
         // And we can return the result
@@ -68,48 +128,143 @@ impl Mul for GFByte {
             byte: accumulator,
         }
     }
+
+    /// Squaring is linear in GF(2^8): it spreads bit i of the input to bit 2i
+    /// of a 15-bit polynomial, which then only needs to be reduced modulo m(x)
+    pub fn square(self) -> Self {
+        let mut spread = 0u16;
+        for bit in 0..8 {
+            spread |= ((self.byte as u16 >> bit) & 1) << (2*bit);
+        }
+        for bit in (8..15).rev() {
+            if spread & (1 << bit) != 0 {
+                spread ^= AES_POLYNOMIAL << (bit - 8);
+            }
+        }
+        Self {
+            byte: spread as u8,
+        }
+    }
+
+    /// Exponentiation is implemented by square-and-multiply
+    pub fn pow(self, mut exponent: u32) -> Self {
+        let mut result = Self::from(1);
+        let mut power = self;
+        while exponent != 0 {
+            if exponent & 1 != 0 {
+                result *= power;
+            }
+            power = power.square();
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /// Since the nonzero elements of GF(2^8) form a group of order 255, the
+    /// multiplicative inverse of x is x^254. As in the AES S-box construction,
+    /// 0x00 is mapped to itself. To avoid running the exponentiation on every
+    /// call, the inverses are computed once and cached in a table.
+    pub fn inverse(self) -> Self {
+        static INVERSE_TABLE: OnceLock<[u8; 256]> = OnceLock::new();
+        let table = INVERSE_TABLE.get_or_init(inverse_table);
+        Self::from(table[self.byte as usize])
+    }
 }
-//
-impl MulAssign for GFByte {
-    fn mul_assign(&mut self, rhs: Self) {
-        *self = self.clone() * rhs
+
+
+// Table of the multiplicative inverses of all bytes, computed by
+// exponentiation. Note that table lookups are not constant-time, since their
+// timing depends on which cache lines are accessed.
+pub fn inverse_table() -> [u8; 256] {
+    let mut table = [0; 256];
+    for (byte, inverse) in table.iter_mut().enumerate() {
+        *inverse = GFByte::from(byte as u8).pow(254).into();
     }
+    table
 }
 
-/// Bytes are displayed as in the AES standard
-impl fmt::Display for GFByte {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:02x}", self.byte)
+
+// Polynomials over GF(2) of degree up to 15 can be represented as 16-bit
+// numbers, and divided using bitwise XOR as subtraction. This returns the
+// remainder of the division of a by b.
+fn poly_mod(mut a: u16, b: u16) -> u16 {
+    assert_ne!(b, 0, "Division by the zero polynomial");
+    let b_degree = 15 - b.leading_zeros();
+    while a != 0 && 15 - a.leading_zeros() >= b_degree {
+        a ^= b << (15 - a.leading_zeros() - b_degree);
     }
+    a
 }
 
-/// Explicit conversions to and from bytes are provided
-impl From<u8> for GFByte {
-    fn from(byte: u8) -> Self {
-        Self {
-            byte: byte,
-        }
+// Greatest common divisor of two polynomials over GF(2), using Euclid's
+// algorithm. The gcd of two coprime polynomials is 1.
+pub fn poly_gcd(mut a: u16, mut b: u16) -> u16 {
+    while b != 0 {
+        let remainder = poly_mod(a, b);
+        a = b;
+        b = remainder;
     }
+    a
 }
-//
-impl Into<u8> for GFByte {
-    fn into(self) -> u8 {
-        self.byte
+
+// A polynomial is irreducible if it has no factor of degree 1 or more, other
+// than itself. Any reducible polynomial has a factor whose degree is at most
+// half of its own, so it suffices to check that none of these divides it.
+pub fn is_irreducible(poly: u16) -> bool {
+    if poly < 2 { return false; }
+    let degree = 15 - poly.leading_zeros();
+    (2u16..1 << (degree/2 + 1)).all(|factor| poly_gcd(poly, factor) == 1)
+}
+
+
+// The nonzero elements of GF(2^8) form a cyclic group of order 255, which is
+// generated by 0x03. Every nonzero byte is thus a power of 0x03, and this table
+// maps each power i (from 0 to 255) to the byte 0x03^i. Note that the last
+// entry wraps around to the first one, 0x03^255 = 0x03^0 = 0x01.
+pub fn antilog_table() -> [u8; 256] {
+    let generator = GFByte::from(0x03);
+    let mut table = [0; 256];
+    let mut power = GFByte::from(0x01);
+    for entry in table.iter_mut() {
+        *entry = power.into();
+        power *= generator;
     }
+    table
 }
 
-/// In addition, we provide some extra specific crypto primitives on bytes
-impl GFByte {
-    /// Applying an S-box to a GFByte applies it to the inner byte
-    pub fn apply_s_box(&mut self, sb: &SBox) {
-        self.byte = sb[self.byte as usize];
+// This is the reverse table, mapping each nonzero byte to its logarithm in
+// base 0x03. Since 0x00 has no logarithm, its entry is set to zero.
+pub fn log_table() -> [u8; 256] {
+    let mut table = [0; 256];
+    for (power, &byte) in antilog_table().iter().enumerate().take(255) {
+        table[byte as usize] = power as u8;
     }
+    table
+}
+
+// Multiplying two bytes amounts to adding their logarithms modulo 255. As with
+// inverses, the tables are computed once and cached, so that this is a faster
+// multiplication path than the shift-and-add one (but not a constant-time one).
+pub fn mul_via_tables(a: u8, b: u8) -> u8 {
+    static LOG_TABLE: OnceLock<[u8; 256]> = OnceLock::new();
+    static ANTILOG_TABLE: OnceLock<[u8; 256]> = OnceLock::new();
+    if a == 0 || b == 0 { return 0; }
+    let log = LOG_TABLE.get_or_init(log_table);
+    let antilog = ANTILOG_TABLE.get_or_init(antilog_table);
+    let log_sum = (log[a as usize] as usize + log[b as usize] as usize) % 255;
+    antilog[log_sum]
 }
 
 
 #[cfg(test)]
 mod tests {
-    use block_ciphers::aes::gf_byte::GFByte;
+    use block_ciphers::aes::gf_byte::{self, GFByte};
+
+    #[test]
+    fn debug() {
+        assert_eq!(format!("{:?}", GFByte::from(0x63)), "GFByte(0x63)");
+        assert_eq!(format!("{:?}", GFByte::from(0x05)), "GFByte(0x05)");
+    }
 
     // Test that GFByte addition works as expected by the AES spec
     #[test]
@@ -127,4 +282,87 @@ mod tests {
         assert_eq!(GFByte::from(0x57) * GFByte::from(0x10), GFByte::from(0x07));
         assert_eq!(GFByte::from(0x57) * GFByte::from(0x13), GFByte::from(0xfe));
     }
+
+    // Test that squaring matches multiplication of a byte by itself
+    #[test]
+    fn square() {
+        for byte in 0..=255u8 {
+            let x = GFByte::from(byte);
+            assert_eq!(x.square(), x * x);
+        }
+    }
+
+    // Test exponentiation and inversion
+    #[test]
+    fn pow_inverse() {
+        let x = GFByte::from(0x57);
+        assert_eq!(x.pow(0), GFByte::from(0x01));
+        assert_eq!(x.pow(3), x * x * x);
+        assert_eq!(GFByte::from(0x53).inverse(), GFByte::from(0xca));
+        assert_eq!(GFByte::from(0x00).inverse(), GFByte::from(0x00));
+        for byte in 1..=255u8 {
+            let x = GFByte::from(byte);
+            assert_eq!(x * x.inverse(), GFByte::from(0x01));
+        }
+    }
+
+    // Test that the inverse table contains the inverses of all bytes
+    #[test]
+    fn inverse_table() {
+        let table = gf_byte::inverse_table();
+        assert_eq!(table[0], 0x00);
+        for byte in 1..=255u8 {
+            let inverse = GFByte::from(table[byte as usize]);
+            assert_eq!(GFByte::from(byte) * inverse, GFByte::from(0x01));
+            assert_eq!(GFByte::from(byte).inverse(), inverse);
+        }
+    }
+
+    // Test that the AES polynomial is irreducible, unlike some others
+    #[test]
+    fn irreducible() {
+        assert_eq!(gf_byte::poly_gcd(0b110, 0b11), 0b11);
+        assert_eq!(gf_byte::poly_gcd(0x11b, 0x1b), 1);
+        assert!(gf_byte::is_irreducible(gf_byte::AES_POLYNOMIAL));
+        assert!(gf_byte::is_irreducible(0x11d));
+        assert!(!gf_byte::is_irreducible(0x11a));
+        assert!(!gf_byte::is_irreducible(0x101));
+    }
+
+    // Test multiplication modulo other polynomials
+    #[test]
+    fn mul_mod() {
+        let (a, b) = (GFByte::from(0x57), GFByte::from(0x83));
+        assert_eq!(a.mul_mod(b, gf_byte::AES_POLYNOMIAL), a * b);
+        assert_ne!(a.mul_mod(b, 0x11d), a * b);
+
+        // Modulo a reducible polynomial, there are zero divisors:
+        // x^8 + 1 = (x + 1)^8, so (x + 1) * (x + 1)^7 = 0
+        let x_plus_1 = GFByte::from(0x03);
+        assert_eq!(x_plus_1.mul_mod(x_plus_1.pow(7), 0x101),
+                   GFByte::from(0x00));
+    }
+
+    // Test that the log/antilog tables are consistent with each other
+    #[test]
+    fn log_tables() {
+        let (log, antilog) = (gf_byte::log_table(), gf_byte::antilog_table());
+        assert_eq!(antilog[0], 0x01);
+        assert_eq!(antilog[1], 0x03);
+        assert_eq!(antilog[255], 0x01);
+        for byte in 1..=255u8 {
+            assert_eq!(antilog[log[byte as usize] as usize], byte);
+        }
+    }
+
+    // Test that table-based multiplication matches the reference one
+    #[test]
+    fn mul_via_tables() {
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                let expected = GFByte::from(a) * GFByte::from(b);
+                assert_eq!(gf_byte::mul_via_tables(a, b), u8::from(expected));
+            }
+        }
+    }
 }
@@ -9,7 +9,7 @@ use std::ops::{Add, AddAssign, Index, IndexMut, Mul, MulAssign};
 
 /// 4-byte words are sometimes interpreted by AES as 4-term polynomials with
 /// coefficients that are finite field elements (i.e. bytes).
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Eq, PartialEq)]
 pub struct GFWord {
     bytes: [GFByte; 4],
 }
@@ -30,7 +30,7 @@ impl Add for GFWord {
 //
 impl AddAssign for GFWord {
     fn add_assign(&mut self, rhs: Self) {
-        *self = self.clone() + rhs;
+        *self = *self + rhs;
     }
 }
 
@@ -55,7 +55,7 @@ impl Mul for GFWord {
 //
 impl MulAssign for GFWord {
     fn mul_assign(&mut self, rhs: Self) {
-        *self = self.clone() * rhs;
+        *self = *self * rhs;
     }
 }
 
@@ -68,6 +68,14 @@ impl fmt::Display for GFWord {
         Ok(())
     }
 }
+//
+/// Debug output shows the bytes in hex, separated by commas
+impl fmt::Debug for GFWord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GFWord({},{},{},{})",
+               self.bytes[0], self.bytes[1], self.bytes[2], self.bytes[3])
+    }
+}
 
 /// Explicit conversions from 32-bit words are provided
 impl From<u32> for GFWord {
@@ -131,17 +139,50 @@ impl GFWord {
 
     // The SubWord function applies the encryption S-box to the bytes in a word
     pub fn sub_word(&self) -> Self {
-        let mut result = self.clone();
+        let mut result = *self;
         result.apply_s_box(&ENC_SBOX);
         result
     }
+
+    // Scaling a word multiplies each of its coefficients by a single byte in
+    // GF(2^8). Unlike the Mul impl, no polynomial reduction is involved.
+    pub fn scale(self, b: GFByte) -> Self {
+        Self {
+            bytes: [self.bytes[0] * b,
+                    self.bytes[1] * b,
+                    self.bytes[2] * b,
+                    self.bytes[3] * b],
+        }
+    }
+
+    // Raise a word to a power, by square-and-multiply on the polynomial
+    // multiplication modulo x^4 + 1
+    pub fn pow(self, mut exponent: u32) -> Self {
+        let mut result = Self::new(1, 0, 0, 0);
+        let mut power = self;
+        while exponent != 0 {
+            if exponent & 1 != 0 {
+                result *= power;
+            }
+            power *= power;
+            exponent >>= 1;
+        }
+        result
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
+    use block_ciphers::aes::gf_byte::GFByte;
     use block_ciphers::aes::gf_word::GFWord;
 
+    #[test]
+    fn debug() {
+        assert_eq!(format!("{:?}", GFWord::from(0x637c777b)),
+                   "GFWord(63,7c,77,7b)");
+    }
+
     // Test that GFWord multiplication works as expected by the AES spec
     #[test]
     fn mul() {
@@ -152,4 +193,29 @@ mod tests {
         let rot = GFWord::new(0, 0, 0, 1);
         assert_eq!(word * rot, GFWord::new(1, 2, 3, 0));
     }
+
+    // Test that scaling a GFWord multiplies each byte independently
+    #[test]
+    fn scale() {
+        let word = GFWord::new(0x01, 0x02, 0x03, 0x04);
+        assert_eq!(word.scale(GFByte::from(0x02)),
+                   GFWord::new(0x02, 0x04, 0x06, 0x08));
+        let word = GFWord::new(0x57, 0x80, 0x00, 0xff);
+        assert_eq!(word.scale(GFByte::from(0x02)),
+                   GFWord::new(0xae, 0x1b, 0x00, 0xe5));
+    }
+
+    // Test GFWord exponentiation, including the order of the MixColumns
+    // polynomial: applying MixColumns 4 times yields the identity, so the
+    // inverse polynomial is the cube of the MixColumns one
+    #[test]
+    fn pow() {
+        let identity = GFWord::new(1, 0, 0, 0);
+        assert_eq!(GFWord::new(0, 0, 0, 1).pow(4), identity);
+        assert_eq!(GFWord::new(0, 0, 0, 1).pow(0), identity);
+        let a = GFWord::new(0x02, 0x01, 0x01, 0x03);
+        assert_eq!(a.pow(2), a * a);
+        assert_eq!(a.pow(3), GFWord::new(0x0e, 0x09, 0x0d, 0x0b));
+        assert_eq!(a.pow(4), identity);
+    }
 }
@@ -0,0 +1,190 @@
+//! NIST publishes known-answer tests (KAT) for AES as .rsp files, which list
+//! test vectors as groups of "NAME = value" lines. This module runs such test
+//! vectors through our AES implementation.
+
+use block_ciphers::aes::{self, Input};
+use blocks::{self, BlockError};
+use hexfile::{self, parse_hex};
+use std::fs::File;
+use std::io::{self, Read};
+
+
+/// Possible errors when running a KAT file
+#[derive(Debug)]
+pub enum KatError {
+    /// The KAT file could not be loaded
+    Loading(io::Error),
+
+    /// A line of the file is not of the form "NAME = value" (lines are
+    /// numbered from 1, as in text editors)
+    Syntax(usize),
+
+    /// A line of the file contains an invalid hexadecimal value
+    InvalidHex(usize, hexfile::Error),
+
+    /// The test vector with some COUNT has a key whose length (in bytes) is
+    /// not supported by AES
+    InvalidKeyLength { count: usize, len: usize },
+
+    /// The test vector with some COUNT has a plaintext or ciphertext whose
+    /// length (in bytes) is not one block
+    InvalidBlockLength { count: usize, len: usize },
+
+    /// Encrypting the plaintext of the test vector with this COUNT did not
+    /// yield the ciphertext
+    EncryptMismatch(usize),
+
+    /// Decrypting the ciphertext of the test vector with this COUNT did not
+    /// yield the plaintext
+    DecryptMismatch(usize),
+}
+
+
+/// Run all the test vectors from a NIST AES KAT file, stopping at the first
+/// error. Vectors are identified by their COUNT field in error reports.
+pub fn run_kat_file(path: &str) -> Result<(), KatError> {
+    let mut contents = String::new();
+    {
+        let mut input_file = File::open(path).map_err(KatError::Loading)?;
+        input_file.read_to_string(&mut contents).map_err(KatError::Loading)?;
+    }
+    run_kat_str(&contents)
+}
+
+// Run all the test vectors from the textual contents of a NIST AES KAT file
+pub fn run_kat_str(contents: &str) -> Result<(), KatError> {
+    let mut count = 0;
+    let mut key = None;
+    let mut plaintext = None;
+    let mut ciphertext = None;
+    for (index, line) in contents.lines().enumerate() {
+        // Skip blank lines, comments and section headers like [ENCRYPT]
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+            continue;
+        }
+
+        // Split the "NAME = value" pairs
+        let mut fields = line.splitn(2, '=');
+        let (name, value) = match (fields.next(), fields.next()) {
+            (Some(name), Some(value)) => (name.trim(), value.trim()),
+            _ => return Err(KatError::Syntax(index + 1)),
+        };
+        let parse = |value| {
+            parse_hex(value).map_err(|e| KatError::InvalidHex(index + 1, e))
+        };
+        match name {
+            "COUNT" => {
+                count = value.parse()
+                             .map_err(|_| KatError::Syntax(index + 1))?;
+                key = None;
+                plaintext = None;
+                ciphertext = None;
+            },
+            "KEY" => key = Some(parse(value)?),
+            "PLAINTEXT" => plaintext = Some(parse(value)?),
+            "CIPHERTEXT" => ciphertext = Some(parse(value)?),
+            _ => {},
+        }
+
+        // Run the test vector as soon as it is complete
+        if let (Some(k), Some(p), Some(c)) = (&key, &plaintext, &ciphertext) {
+            run_vector(count, k, p, c)?;
+            key = None;
+            plaintext = None;
+            ciphertext = None;
+        }
+    }
+    Ok(())
+}
+
+// Run a single test vector, in both the encryption and decryption directions
+fn run_vector(count: usize,
+              key: &[u8],
+              plaintext: &[u8],
+              ciphertext: &[u8]) -> Result<(), KatError> {
+    // Expand the key
+    let round_keys = aes::try_key_expansion(key)
+                         .map_err(|_| KatError::InvalidKeyLength {
+                             count,
+                             len: key.len(),
+                         })?;
+
+    // Check the test vector's block lengths
+    let as_block = |bytes| {
+        blocks::try_as_block_128u8(bytes).map_err(|e| match e {
+            BlockError::WrongLength { got, .. } => {
+                KatError::InvalidBlockLength { count, len: got }
+            },
+        })
+    };
+    let plaintext: &Input = as_block(plaintext)?;
+    let ciphertext: &Input = as_block(ciphertext)?;
+
+    // Check both directions of the cipher
+    if aes::cipher(plaintext, &round_keys) != *ciphertext {
+        return Err(KatError::EncryptMismatch(count));
+    }
+    if aes::inv_cipher(ciphertext, &round_keys) != *plaintext {
+        return Err(KatError::DecryptMismatch(count));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use block_ciphers::aes::kat::{self, KatError};
+
+    // Test vectors from FIPS-197 appendix C, in NIST's .rsp format
+    const KAT_SNIPPET: &str = "\
+# FIPS-197 appendix C example vectors
+
+[ENCRYPT]
+
+COUNT = 0
+KEY = 000102030405060708090a0b0c0d0e0f
+PLAINTEXT = 00112233445566778899aabbccddeeff
+CIPHERTEXT = 69c4e0d86a7b0430d8cdb78070b4c55a
+
+COUNT = 1
+KEY = 000102030405060708090a0b0c0d0e0f1011121314151617
+PLAINTEXT = 00112233445566778899aabbccddeeff
+CIPHERTEXT = dda97ca4864cdfe06eaf70a0ec0d7191
+
+[DECRYPT]
+
+COUNT = 2
+KEY = 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f
+CIPHERTEXT = 8ea2b7ca516745bfeafc49904b496089
+PLAINTEXT = 00112233445566778899aabbccddeeff
+";
+
+    #[test]
+    fn valid_snippet() {
+        assert!(kat::run_kat_str(KAT_SNIPPET).is_ok());
+    }
+
+    #[test]
+    fn mismatch() {
+        let corrupted = KAT_SNIPPET.replace("dda97ca4", "dda97ca5");
+        match kat::run_kat_str(&corrupted) {
+            Err(KatError::EncryptMismatch(1)) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        let short_key = KAT_SNIPPET.replace("0c0d0e0f\n", "0c0d0e\n");
+        match kat::run_kat_str(&short_key) {
+            Err(KatError::InvalidKeyLength { count: 0, len: 15 }) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let short_block = KAT_SNIPPET.replace("4b496089", "4b4960");
+        match kat::run_kat_str(&short_block) {
+            Err(KatError::InvalidBlockLength { count: 2, len: 15 }) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
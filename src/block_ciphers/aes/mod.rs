@@ -2,11 +2,21 @@
 
 mod gf_byte;
 mod gf_word;
+mod kat;
 mod state;
 
+pub use block_ciphers::aes::gf_byte::{AES_POLYNOMIAL, antilog_table,
+                                      inverse_table, is_irreducible,
+                                      log_table, mul_via_tables, poly_gcd};
+pub use block_ciphers::aes::kat::{KatError, run_kat_file, run_kat_str};
+
+use block_ciphers::{DecryptBlock128, EncryptBlock128, InverseCipher128};
 use block_ciphers::aes::gf_word::GFWord;
 use block_ciphers::aes::state::{N_B, State};
 use blocks::Block128u8;
+use hash::sha_256;
+use hexfile;
+use std::ptr;
 
 
 // ### BASIC DATA STRUCTURES ###
@@ -21,7 +31,7 @@ pub type Key192 = [u8; 192/8];
 pub type Key256 = [u8; 256/8];
 
 // AES also uses byte substitution tables, aka S-boxes
-type SBox = [u8; 256];
+pub type SBox = [u8; 256];
 
 
 // ### S-BOXES USED BY AES ###
@@ -99,25 +109,62 @@ const DEC_SBOX: SBox = [0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38,
                         0x17, 0x2b, 0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 
                         0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d];
 
+// Since I brute-forced the decryption S-box, it is worth double-checking that
+// it was transcribed correctly. This is done at compile time by deriving the
+// inverse of the encryption S-box and comparing it with the decryption S-box.
+// This inversion works for any bijective S-box, so it is public.
+pub const fn invert_sbox(s_box: &SBox) -> SBox {
+    let mut result = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        result[s_box[i] as usize] = i as u8;
+        i += 1;
+    }
+    result
+}
+//
+const fn s_boxes_equal(s_box_1: &SBox, s_box_2: &SBox) -> bool {
+    let mut i = 0;
+    while i < 256 {
+        if s_box_1[i] != s_box_2[i] { return false; }
+        i += 1;
+    }
+    true
+}
+//
+const _: () = assert!(s_boxes_equal(&invert_sbox(&ENC_SBOX), &DEC_SBOX),
+                      "DEC_SBOX is not the inverse of ENC_SBOX");
+
 
 // ### KEY EXPANSION ###
 
 // AES keys are expanded into a set of round keys. The amount of encryption
 // rounds Nr, which determins the amount of round keys, depends on the key size.
+pub const N_R_128: usize = 10;
+pub const N_R_192: usize = 12;
+pub const N_R_256: usize = 14;
+//
 type RoundKeys = [GFWord];
-pub type RoundKeys128 = [GFWord; N_B*(10+1)];  // Nr = 10 for 128-bit keys
-pub type RoundKeys192 = [GFWord; N_B*(12+1)];  // Nr = 12 for 192-bit keys
-pub type RoundKeys256 = [GFWord; N_B*(14+1)];  // Nr = 14 for 256-bit keys
+pub type RoundKeys128 = [GFWord; N_B*(N_R_128+1)];
+pub type RoundKeys192 = [GFWord; N_B*(N_R_192+1)];
+pub type RoundKeys256 = [GFWord; N_B*(N_R_256+1)];
+
+// Map a key length in bytes to the associated amount of encryption rounds
+pub fn num_rounds(key_len_bytes: usize) -> usize {
+    match key_len_bytes {
+        16 => N_R_128,
+        24 => N_R_192,
+        32 => N_R_256,
+        _ => panic!("Invalid AES key length: {} bytes", key_len_bytes),
+    }
+}
 
 // Here is a generic key expansion routine. It works by taking up the slice of
 // keys and writing into the slice of round keys.
 fn key_expansion(key: &[u8], w: &mut RoundKeys) {
-    // Retrieve Nk from the length of the key slice
-    assert_eq!(key.len() % 4, 0);
+    // Retrieve Nk and Nr from the length of the key slice
     let n_k = key.len() / 4;
-
-    // Determine Nr (for AES, Nr = Nk + 6 should always hold)
-    let n_r = n_k + 6;
+    let n_r = num_rounds(key.len());
     assert_eq!(w.len(), N_B*(n_r+1));
 
     // Compute the round constants. Ideally, these should be global constants,
@@ -146,36 +193,188 @@ fn key_expansion(key: &[u8], w: &mut RoundKeys) {
     }
 }
 
-// From the routine above, we can build the 128-bit key expansion routine...
+/// Possible errors when expanding a key of dynamic size
+#[derive(Debug, Eq, PartialEq)]
+pub enum KeyError {
+    /// AES keys must be 16, 24 or 32 bytes long, but this one is not
+    InvalidKeyLength(usize),
+}
+
+// When the key size is only known at runtime, this key expansion routine can
+// be used instead. It validates the key length and allocates a schedule of the
+// appropriate size.
+pub fn try_key_expansion(key: &[u8]) -> Result<Vec<GFWord>, KeyError> {
+    match key.len() {
+        16 | 24 | 32 => {},
+        other => return Err(KeyError::InvalidKeyLength(other)),
+    }
+    let mut result = vec![GFWord::zero(); N_B*(num_rounds(key.len())+1)];
+    key_expansion(key, &mut result[..]);
+    Ok(result)
+}
+
+/// Possible errors when loading a key from a hex file
+#[derive(Debug)]
+pub enum KeyLoadError {
+    /// The key could not be loaded from a hex file
+    Loading(hexfile::Error),
+
+    /// The hex file does not hold a key of the expected size
+    InvalidKeyLength(usize),
+}
+
+// Keys for the course tooling are provided as hex files, whose length must
+// match the expected key size
+pub fn load_key_128(filename: &str) -> Result<Key128, KeyLoadError> {
+    load_key(filename)
+}
+//
+pub fn load_key_192(filename: &str) -> Result<Key192, KeyLoadError> {
+    load_key(filename)
+}
+//
+pub fn load_key_256(filename: &str) -> Result<Key256, KeyLoadError> {
+    load_key(filename)
+}
+//
+fn load_key<const N: usize>(filename: &str)
+    -> Result<[u8; N], KeyLoadError>
+{
+    let bytes = hexfile::load_bytes(filename).map_err(KeyLoadError::Loading)?;
+    if bytes.len() != N {
+        return Err(KeyLoadError::InvalidKeyLength(bytes.len()));
+    }
+    let mut key = [0; N];
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
+// Keys where all bytes are equal (such as the all-zero key) are typically
+// placeholders that someone forgot to replace with a real key. This check lets
+// tooling warn users who are about to encrypt with such a key.
+pub fn is_trivial_key(key: &[u8]) -> bool {
+    key.windows(2).all(|pair| pair[0] == pair[1])
+}
+
+// For quick experiments, a key can be derived from a passphrase by hashing it
+// with SHA-256. This is NOT a proper key derivation function: it is fast and
+// unsalted, so it makes brute-forcing human passwords easy. Real passwords
+// should go through a KDF such as PBKDF2 or Argon2 instead.
+pub fn key_from_passphrase_128(passphrase: &[u8]) -> Key128 {
+    let digest = sha_256::sha_256(passphrase);
+    *array_ref!(digest, 0, 128/8)
+}
+//
+pub fn key_from_passphrase_256(passphrase: &[u8]) -> Key256 {
+    sha_256::sha_256(passphrase)
+}
+
+// From the generic routine, we can build the 128-bit key expansion routine...
 pub fn key_expansion_128(key: &Key128) -> RoundKeys128 {
-    let mut result = [GFWord::zero(); N_B*(10+1)];
+    let mut result = [GFWord::zero(); N_B*(N_R_128+1)];
     key_expansion(&key[..], &mut result[..]);
     result
 }
 
 // ...the 192-bit key expansion routine...
 pub fn key_expansion_192(key: &Key192) -> RoundKeys192 {
-    let mut result = [GFWord::zero(); N_B*(12+1)];
+    let mut result = [GFWord::zero(); N_B*(N_R_192+1)];
     key_expansion(&key[..], &mut result[..]);
     result
 }
 
 // ...and the 256-bit key expansion routine
 pub fn key_expansion_256(key: &Key256) -> RoundKeys256 {
-    let mut result = [GFWord::zero(); N_B*(14+1)];
+    let mut result = [GFWord::zero(); N_B*(N_R_256+1)];
     key_expansion(&key[..], &mut result[..]);
     result
 }
 
+// The first Nk words of the round keys are the original key itself, so it
+// can be recovered from the key schedule (e.g. when debugging it)
+pub fn recover_key_128(round_keys: &RoundKeys128) -> Key128 {
+    let mut key = [0; 128/8];
+    for (word, key_bytes) in round_keys.iter().zip(key.chunks_mut(4)) {
+        for (index, byte) in key_bytes.iter_mut().enumerate() {
+            *byte = word[index].into();
+        }
+    }
+    key
+}
+
+// When debugging a key expansion (e.g. one performed by hand), it helps to know
+// where it diverges from the reference. This returns the indices of the words
+// which differ between two key schedules. If the schedules do not have the
+// same length, the extra words of the longer one are reported as different.
+pub fn diff_schedules(a: &[GFWord], b: &[GFWord]) -> Vec<usize> {
+    (0..a.len().max(b.len())).filter(|&index| a.get(index) != b.get(index))
+                             .collect()
+}
+
 
 // ### ENCRYPTION AND DECRYPTION ###
 
 // The AES cipher
 pub fn cipher(input: &Input, round_keys: &RoundKeys) -> Output {
+    cipher_impl(input, round_keys, usize::MAX, true, None)
+}
+
+// To study how much of the diffusion of AES comes from MixColumns, this variant
+// of the cipher skips it. Each output byte then only depends on one input byte.
+pub fn cipher_no_mixcolumns(input: &Input, round_keys: &RoundKeys) -> Output {
+    cipher_impl(input, round_keys, usize::MAX, false, None)
+}
+
+// To compare partial encryptions, this variant of the cipher stops after the
+// requested amount of rounds and returns the intermediate state. Zero rounds
+// only XORs the initial round key, and the amount of rounds is clamped to Nr,
+// at which point the output is that of the full cipher.
+pub fn cipher_rounds(input: &Input,
+                     round_keys: &RoundKeys,
+                     rounds: usize) -> Output {
+    cipher_impl(input, round_keys, rounds, true, None)
+}
+
+// Differential fault analysis recovers the key by comparing correct and faulty
+// ciphertexts, where the faults are injected at a known round of the cipher.
+// This describes a fault which XORs a mask into one byte of the state (indexed
+// as in the input block) at the start of a round, before SubBytes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Fault {
+    pub round: usize,
+    pub byte_index: usize,
+    pub xor_mask: u8,
+}
+//
+pub fn cipher_with_fault(input: &Input,
+                         round_keys: &RoundKeys,
+                         fault: Fault) -> Output {
+    cipher_impl(input, round_keys, usize::MAX, true, Some(fault))
+}
+
+// Cipher implementation, where the amount of rounds may be reduced, the
+// MixColumns step may be disabled, and a fault may be injected
+fn cipher_impl(input: &Input,
+               round_keys: &RoundKeys,
+               rounds: usize,
+               mix_columns: bool,
+               fault: Option<Fault>) -> Output {
     // Make sure that the amount of round keys is sensical
     assert_eq!(round_keys.len() % N_B, 0);
     assert!(round_keys.len() > N_B);
     let n_r = round_keys.len()/N_B - 1;
+    let rounds = rounds.min(n_r);
+    if let Some(fault) = fault {
+        assert!(fault.round >= 1 && fault.round <= n_r, "Invalid fault round");
+        assert!(fault.byte_index < 4*N_B, "Invalid fault byte index");
+    }
+    let inject_fault = |state: &mut State, round: usize| {
+        if let Some(fault) = fault {
+            if fault.round == round {
+                state.xor_byte(fault.byte_index, fault.xor_mask);
+            }
+        }
+    };
 
     // Initialize the AES state from the input data
     let mut state = State::from(input);
@@ -184,17 +383,21 @@ pub fn cipher(input: &Input, round_keys: &RoundKeys) -> Output {
     state.add_round_key(&round_keys[0..N_B]);
 
     // Perform the following encryption rounds
-    for round in 1..n_r {
+    for round in 1..=rounds.min(n_r-1) {
+        inject_fault(&mut state, round);
         state.sub_bytes();
         state.shift_rows();
-        state.mix_columns();
+        if mix_columns { state.mix_columns(); }
         state.add_round_key(&round_keys[(round*N_B)..((round+1)*N_B)]);
     }
 
-    // Apply the final transformations
-    state.sub_bytes();
-    state.shift_rows();
-    state.add_round_key(&round_keys[(n_r*N_B)..(n_r+1)*N_B]);
+    // Apply the final transformations, if requested
+    if rounds == n_r {
+        inject_fault(&mut state, n_r);
+        state.sub_bytes();
+        state.shift_rows();
+        state.add_round_key(&round_keys[(n_r*N_B)..(n_r+1)*N_B]);
+    }
 
     // Extract the final state and return it as our output
     state.into()
@@ -231,10 +434,266 @@ pub fn inv_cipher(input: &Input, round_keys: &RoundKeys) -> Output {
 }
 
 
+// Render a step-by-step AES-128 encryption, in the spirit of the FIPS-197
+// appendix B worked example. For each round, the state is displayed as a grid
+// at the start of the round and after each transformation, along with the
+// round key which is added at the end of the round.
+pub fn worked_example(input: &Input, key: &Key128) -> String {
+    let round_keys = key_expansion_128(key);
+    let round_key = |round: usize| {
+        let mut key_state = State::from(&[0; 4*N_B]);
+        key_state.add_round_key(&round_keys[(round*N_B)..((round+1)*N_B)]);
+        key_state
+    };
+    let mut result = String::new();
+    let mut push_step = |title: &str, state: &State| {
+        result.push_str(title);
+        result.push_str(":\n");
+        result.push_str(&state.to_grid_string());
+    };
+
+    // Initial round key addition
+    let mut state = State::from(input);
+    push_step("Input", &state);
+    push_step("Round key 0", &round_key(0));
+    state.add_round_key(&round_keys[0..N_B]);
+
+    // Encryption rounds, the last of which has no MixColumns step
+    for round in 1..=N_R_128 {
+        push_step(&format!("Round {} start", round), &state);
+        state.sub_bytes();
+        push_step("After SubBytes", &state);
+        state.shift_rows();
+        push_step("After ShiftRows", &state);
+        if round != N_R_128 {
+            state.mix_columns();
+            push_step("After MixColumns", &state);
+        }
+        push_step(&format!("Round key {}", round), &round_key(round));
+        state.add_round_key(&round_keys[(round*N_B)..((round+1)*N_B)]);
+    }
+    push_step("Output", &state);
+    result
+}
+
+
+// Run the FIPS-197 example vectors (appendix B, then the AES-128, AES-192 and
+// AES-256 vectors of appendix C) in both directions, as a power-on self-test
+// which programs embedding this implementation can run at startup. On failure,
+// the first failing vector is named in the error.
+pub fn self_test() -> Result<(), &'static str> {
+    const FIPS_197_PLAINTEXT: Input = [0x00, 0x11, 0x22, 0x33,
+                                       0x44, 0x55, 0x66, 0x77,
+                                       0x88, 0x99, 0xaa, 0xbb,
+                                       0xcc, 0xdd, 0xee, 0xff];
+    const SEQUENTIAL_KEY: [u8; 32] = [0x00, 0x01, 0x02, 0x03,
+                                      0x04, 0x05, 0x06, 0x07,
+                                      0x08, 0x09, 0x0a, 0x0b,
+                                      0x0c, 0x0d, 0x0e, 0x0f,
+                                      0x10, 0x11, 0x12, 0x13,
+                                      0x14, 0x15, 0x16, 0x17,
+                                      0x18, 0x19, 0x1a, 0x1b,
+                                      0x1c, 0x1d, 0x1e, 0x1f];
+    let vectors: [(&'static str, &[u8], Input, Output); 4] = [
+        ("FIPS-197 appendix B",
+         &[0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+           0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c],
+         [0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d,
+          0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34],
+         [0x39, 0x25, 0x84, 0x1d, 0x02, 0xdc, 0x09, 0xfb,
+          0xdc, 0x11, 0x85, 0x97, 0x19, 0x6a, 0x0b, 0x32]),
+        ("FIPS-197 appendix C.1 (AES-128)",
+         &SEQUENTIAL_KEY[..16],
+         FIPS_197_PLAINTEXT,
+         [0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30,
+          0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5, 0x5a]),
+        ("FIPS-197 appendix C.2 (AES-192)",
+         &SEQUENTIAL_KEY[..24],
+         FIPS_197_PLAINTEXT,
+         [0xdd, 0xa9, 0x7c, 0xa4, 0x86, 0x4c, 0xdf, 0xe0,
+          0x6e, 0xaf, 0x70, 0xa0, 0xec, 0x0d, 0x71, 0x91]),
+        ("FIPS-197 appendix C.3 (AES-256)",
+         &SEQUENTIAL_KEY[..],
+         FIPS_197_PLAINTEXT,
+         [0x8e, 0xa2, 0xb7, 0xca, 0x51, 0x67, 0x45, 0xbf,
+          0xea, 0xfc, 0x49, 0x90, 0x4b, 0x49, 0x60, 0x89]),
+    ];
+    for &(name, key, ref plaintext, ref ciphertext) in vectors.iter() {
+        let round_keys = try_key_expansion(key).map_err(|_| name)?;
+        if cipher(plaintext, &round_keys) != *ciphertext
+           || inv_cipher(ciphertext, &round_keys) != *plaintext {
+            return Err(name);
+        }
+    }
+    Ok(())
+}
+
+// ### KEYED CIPHERS ###
+
+// AES-128 may be used through an object which holds the key schedule. Batch
+// encryption of several messages with it is provided by the modes module.
+pub struct Aes128 {
+    round_keys: RoundKeys128,
+}
+//
+impl Aes128 {
+    // The key schedule is computed on construction
+    pub fn new(key: &Key128) -> Self {
+        Self {
+            round_keys: key_expansion_128(key),
+        }
+    }
+
+    // Blocks may be encrypted and decrypted into new blocks...
+    pub fn encrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        cipher(block, &self.round_keys)
+    }
+    //
+    pub fn decrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        inv_cipher(block, &self.round_keys)
+    }
+
+    // ...or in place, which is convenient for in-place modes of operation
+    pub fn encrypt_block_in_place(&self, block: &mut Block128u8) {
+        *block = cipher(block, &self.round_keys);
+    }
+    //
+    pub fn decrypt_block_in_place(&self, block: &mut Block128u8) {
+        *block = inv_cipher(block, &self.round_keys);
+    }
+}
+//
+/// AES-128 objects may be directly used as forward ciphers...
+impl EncryptBlock128 for Aes128 {
+    fn encrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        Aes128::encrypt_block(self, block)
+    }
+}
+//
+/// ...and as inverse ciphers
+impl DecryptBlock128 for Aes128 {
+    fn decrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        Aes128::decrypt_block(self, block)
+    }
+}
+
+// Block cipher modes of operation expect a closure which maps an input block
+// to an output block. The following builders produce such closures from a
+// 128-bit key, capturing only the key schedule, with the inverse cipher being
+// wrapped so that it can only be used for decryption. The copy of the key
+// which is used for key expansion is erased before returning.
+pub fn keyed_cipher_128(key: &Key128) -> impl Fn(&Block128u8) -> Block128u8 {
+    let round_keys = erasing_key_expansion_128(key);
+    move |input: &Input| -> Output { cipher(input, &round_keys) }
+}
+//
+pub fn keyed_inv_cipher_128(key: &Key128)
+    -> InverseCipher128<impl Fn(&Block128u8) -> Block128u8>
+{
+    let round_keys = erasing_key_expansion_128(key);
+    InverseCipher128::new(move |input: &Input| -> Output {
+        inv_cipher(input, &round_keys)
+    })
+}
+
+// Expand a copy of a 128-bit key, then overwrite that copy with zeroes. We use
+// volatile writes so that the compiler does not optimize the erasure out.
+fn erasing_key_expansion_128(key: &Key128) -> RoundKeys128 {
+    let mut key_copy = *key;
+    let round_keys = key_expansion_128(&key_copy);
+    for byte in key_copy.iter_mut() {
+        unsafe { ptr::write_volatile(byte, 0); }
+    }
+    round_keys
+}
+
+
 #[cfg(test)]
 mod tests {
+    use block_ciphers::DecryptBlock128;
     use block_ciphers::aes;
     use block_ciphers::aes::gf_word::GFWord;
+    use test_utils::TempFile;
+
+    // Check that the decryption S-box is the inverse of the encryption S-box
+    #[test]
+    fn s_box_inverse() {
+        for i in 0..256 {
+            assert_eq!(aes::DEC_SBOX[aes::ENC_SBOX[i] as usize] as usize, i);
+            assert_eq!(aes::ENC_SBOX[aes::DEC_SBOX[i] as usize] as usize, i);
+        }
+        assert_eq!(&aes::invert_sbox(&aes::ENC_SBOX)[..], &aes::DEC_SBOX[..]);
+        assert_eq!(&aes::invert_sbox(&aes::DEC_SBOX)[..], &aes::ENC_SBOX[..]);
+    }
+
+    // Check that the amount of rounds matches the spec for each key size
+    #[test]
+    fn num_rounds() {
+        assert_eq!(aes::num_rounds(16), 10);
+        assert_eq!(aes::num_rounds(24), 12);
+        assert_eq!(aes::num_rounds(32), 14);
+    }
+
+    // Check that dynamic key expansion agrees with the static one, and rejects
+    // invalid key lengths
+    #[test]
+    fn try_key_expansion() {
+        let key = (0..32).collect::<Vec<u8>>();
+        assert_eq!(aes::try_key_expansion(&key[..16]).unwrap(),
+                   aes::key_expansion_128(array_ref!(key, 0, 16)).to_vec());
+        assert_eq!(aes::try_key_expansion(&key[..24]).unwrap(),
+                   aes::key_expansion_192(array_ref!(key, 0, 24)).to_vec());
+        assert_eq!(aes::try_key_expansion(&key[..32]).unwrap(),
+                   aes::key_expansion_256(array_ref!(key, 0, 32)).to_vec());
+        assert_eq!(aes::try_key_expansion(&key[..20]),
+                   Err(aes::KeyError::InvalidKeyLength(20)));
+    }
+
+    // Check that keys can be loaded from hex files of the right length only
+    #[test]
+    fn load_key() {
+        let file = TempFile::new(b"000102030405060708090a0b0c0d0e0f\n");
+        assert_eq!(aes::load_key_128(file.path()).unwrap(),
+                   [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+                    0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f]);
+
+        let file =
+            TempFile::new(b"000102030405060708090a0b0c0d0e0f10111213\n");
+        match aes::load_key_128(file.path()) {
+            Err(aes::KeyLoadError::InvalidKeyLength(20)) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match aes::load_key_192(file.path()) {
+            Err(aes::KeyLoadError::InvalidKeyLength(20)) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match aes::load_key_256("/nonexistent/coursera_crypto.hex") {
+            Err(aes::KeyLoadError::Loading(_)) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    // Check that placeholder keys are detected
+    #[test]
+    fn is_trivial_key() {
+        assert!(aes::is_trivial_key(&[0x00; 16]));
+        assert!(aes::is_trivial_key(&[0xff; 32]));
+        assert!(!aes::is_trivial_key(&[0x2b, 0x7e, 0x15, 0x16,
+                                       0x28, 0xae, 0xd2, 0xa6,
+                                       0xab, 0xf7, 0x15, 0x88,
+                                       0x09, 0xcf, 0x4f, 0x3c]));
+    }
+
+    // Check that passphrase-derived keys are deterministic prefixes of SHA-256
+    #[test]
+    fn key_from_passphrase() {
+        let key_128 = aes::key_from_passphrase_128(b"correct horse");
+        let key_256 = aes::key_from_passphrase_256(b"correct horse");
+        assert_eq!(key_128, aes::key_from_passphrase_128(b"correct horse"));
+        assert_eq!(key_256, aes::key_from_passphrase_256(b"correct horse"));
+        assert_eq!(&key_128[..], &key_256[..16]);
+        assert_ne!(key_128, aes::key_from_passphrase_128(b"battery staple"));
+    }
 
     // Check that 128-bit key expansion from appendix A works as expected
     #[test]
@@ -268,6 +727,84 @@ mod tests {
         assert_eq!(&actual[..], &expected[..]);
     }
 
+    // Check that faults are injected where requested
+    #[test]
+    fn cipher_with_fault() {
+        let round_keys = aes::key_expansion_128(&[0x42; 16]);
+        let input = [0x24; 16];
+        let correct = aes::cipher(&input, &round_keys);
+        let faulty = |round, xor_mask| {
+            let fault = aes::Fault { round, byte_index: 5, xor_mask };
+            aes::cipher_with_fault(&input, &round_keys, fault)
+        };
+        assert_eq!(faulty(9, 0x00), correct);
+
+        // A fault before the last round only affects one output byte, since
+        // the last round has no MixColumns (ShiftRows moves byte 5 to byte 1)
+        let last_round_diff = faulty(10, 0x01).iter()
+                                              .zip(correct.iter())
+                                              .map(|(a, b)| a ^ b)
+                                              .collect::<Vec<_>>();
+        for (index, &diff) in last_round_diff.iter().enumerate() {
+            assert_eq!(diff != 0, index == 1);
+        }
+
+        // A fault before the 9th round spreads to a full column
+        let diff_count = faulty(9, 0x01).iter()
+                                        .zip(correct.iter())
+                                        .filter(|(a, b)| a != b)
+                                        .count();
+        assert_eq!(diff_count, 4);
+    }
+
+    // Check that key schedule differences are located correctly
+    #[test]
+    fn diff_schedules() {
+        let reference = aes::key_expansion_128(&[0x2b, 0x7e, 0x15, 0x16,
+                                                 0x28, 0xae, 0xd2, 0xa6,
+                                                 0xab, 0xf7, 0x15, 0x88,
+                                                 0x09, 0xcf, 0x4f, 0x3c]);
+        let mut altered = reference;
+        altered[17] = GFWord::from(0x88542cb2);
+        assert!(aes::diff_schedules(&reference, &reference).is_empty());
+        assert_eq!(aes::diff_schedules(&reference, &altered), vec![17]);
+        assert_eq!(aes::diff_schedules(&reference[..42], &altered[..40]),
+                   vec![17, 40, 41]);
+    }
+
+    // Check that partial encryptions behave as expected at both ends
+    #[test]
+    fn cipher_rounds() {
+        let key = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+                   0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+        let input = [0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d,
+                     0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34];
+        let round_keys = aes::key_expansion_128(&key);
+        let full = aes::cipher(&input, &round_keys);
+        assert_eq!(aes::cipher_rounds(&input, &round_keys, aes::N_R_128), full);
+        assert_eq!(aes::cipher_rounds(&input, &round_keys, 100), full);
+        assert_ne!(aes::cipher_rounds(&input, &round_keys, 9), full);
+
+        // From FIPS-197 appendix B, the state at the start of round 2
+        assert_eq!(aes::cipher_rounds(&input, &round_keys, 1),
+                   [0xa4, 0x9c, 0x7f, 0xf2, 0x68, 0x9f, 0x35, 0x2b,
+                    0x6b, 0x5b, 0xea, 0x43, 0x02, 0x6a, 0x50, 0x49]);
+        let mut initial = input;
+        for (byte, key_byte) in initial.iter_mut().zip(key.iter()) {
+            *byte ^= key_byte;
+        }
+        assert_eq!(aes::cipher_rounds(&input, &round_keys, 0), initial);
+    }
+
+    // Check that the key can be recovered from its expansion
+    #[test]
+    fn recover_key_128() {
+        let key = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+                   0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+        let round_keys = aes::key_expansion_128(&key);
+        assert_eq!(aes::recover_key_128(&round_keys), key);
+    }
+
     // Check that 192-bit key expansion from appendix A works as expected
     #[test]
     fn key_expansion_192() {
@@ -364,6 +901,35 @@ mod tests {
                             0xdc, 0x11, 0x85, 0x97, 0x19, 0x6a, 0x0b, 0x32]);
     }
 
+    // Check the worked example against the intermediate values of appendix B
+    #[test]
+    fn worked_example() {
+        let input = [0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d,
+                     0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34];
+        let key = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+                   0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+        let example = aes::worked_example(&input, &key);
+        assert!(example.starts_with("Input:\n32 88 31 e0\n43 5a 31 37\n\
+                                     f6 30 98 07\na8 8d a2 34\n\
+                                     Round key 0:\n2b 28 ab 09\n"));
+        assert!(example.contains("Round 1 start:\n19 a0 9a e9\n3d f4 c6 f8\n\
+                                  e3 e2 8d 48\nbe 2b 2a 08\n\
+                                  After SubBytes:\nd4 e0 b8 1e\n27 bf b4 41\n\
+                                  11 98 5d 52\nae f1 e5 30\n\
+                                  After ShiftRows:\nd4 e0 b8 1e\nbf b4 41 27\n\
+                                  5d 52 11 98\n30 ae f1 e5\n\
+                                  After MixColumns:\n04 e0 48 28\n66 cb f8 06\n\
+                                  81 19 d3 26\ne5 9a 7a 4c\n\
+                                  Round key 1:\na0 88 23 2a\nfa 54 a3 6c\n\
+                                  fe 2c 39 76\n17 b1 39 05\n"));
+        assert!(example.contains("After ShiftRows:\ne9 cb 3d af\n\
+                                  31 32 2e 09\n7d 2c 89 07\nb5 72 5f 94\n\
+                                  Round key 10:"));
+        assert!(example.ends_with("Output:\n39 02 dc 19\n25 dc 11 6a\n\
+                                   84 09 85 0b\n1d fb 97 32\n"));
+        assert_eq!(example.matches("After MixColumns").count(), 9);
+    }
+
     // Check that the standard AES test vectors from appendix C work as expected
     #[test]
     fn example_vectors() {
@@ -410,4 +976,42 @@ mod tests {
                     0xea, 0xfc, 0x49, 0x90, 0x4b, 0x49, 0x60, 0x89]);
         assert_eq!(aes::inv_cipher(&cipher_256, &key_256), plaintext);
     }
+
+    // Check that the in-place and copying AES-128 methods agree
+    #[test]
+    fn aes_128_in_place() {
+        let aes_128 = aes::Aes128::new(&[0x2b, 0x7e, 0x15, 0x16,
+                                         0x28, 0xae, 0xd2, 0xa6,
+                                         0xab, 0xf7, 0x15, 0x88,
+                                         0x09, 0xcf, 0x4f, 0x3c]);
+        let plaintext = [0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d,
+                         0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34];
+        let ciphertext = aes_128.encrypt_block(&plaintext);
+        let mut block = plaintext;
+        aes_128.encrypt_block_in_place(&mut block);
+        assert_eq!(block, ciphertext);
+        aes_128.decrypt_block_in_place(&mut block);
+        assert_eq!(block, plaintext);
+        assert_eq!(aes_128.decrypt_block(&ciphertext), plaintext);
+    }
+
+    #[test]
+    fn self_test() {
+        assert_eq!(aes::self_test(), Ok(()));
+    }
+
+    // Check that the keyed cipher closures match the raw cipher functions
+    #[test]
+    fn keyed_cipher_128() {
+        let key = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+                   0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+        let plaintext = [0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d,
+                         0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34];
+        let round_keys = aes::key_expansion_128(&key);
+        let keyed_cipher = aes::keyed_cipher_128(&key);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&key);
+        let ciphertext = keyed_cipher(&plaintext);
+        assert_eq!(ciphertext, aes::cipher(&plaintext, &round_keys));
+        assert_eq!(keyed_inv_cipher.decrypt_block(&ciphertext), plaintext);
+    }
 }
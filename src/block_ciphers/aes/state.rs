@@ -4,7 +4,9 @@
 //! an input or output block of the cipher.
 
 use block_ciphers::aes::{DEC_SBOX, ENC_SBOX, Input, Output, RoundKeys, SBox};
+use block_ciphers::aes::gf_byte::GFByte;
 use block_ciphers::aes::gf_word::GFWord;
+use blocks::{self, BlockError};
 use std::fmt;
 
 
@@ -30,15 +32,18 @@ impl<'a> From<&'a Input> for State {
     }
 }
 
+/// When the input is only known at runtime (e.g. after parsing hex), the state
+/// may also be built from a slice, whose length is then checked
+impl State {
+    pub fn from_slice(slice: &[u8]) -> Result<Self, BlockError> {
+        blocks::try_as_block_128u8(slice).map(Self::from)
+    }
+}
+
 /// ...and eventually turned back into an output block
-impl Into<Output> for State {
-    fn into(self) -> Output {
-        let (w1, w2) = (self.words[0], self.words[1]);
-        let (w3, w4) = (self.words[2], self.words[3]);
-        [w1[0].into(), w1[1].into(), w1[2].into(), w1[3].into(),
-         w2[0].into(), w2[1].into(), w2[2].into(), w2[3].into(),
-         w3[0].into(), w3[1].into(), w3[2].into(), w3[3].into(),
-         w4[0].into(), w4[1].into(), w4[2].into(), w4[3].into()]
+impl From<State> for Output {
+    fn from(state: State) -> Self {
+        state.bytes()
     }
 }
 
@@ -56,6 +61,44 @@ impl fmt::Display for State {
 /// The AES encryption and decryption algorithms are specified in terms of
 /// operations on the internal state:
 impl State {
+    /// Render the state as the 4x4 column-major grid of bytes used by the AES
+    /// spec, which eases comparisons with the FIPS-197 worked examples
+    pub fn to_grid_string(&self) -> String {
+        let mut result = String::with_capacity(4 * 3 * N_B);
+        for row in 0..4 {
+            for (col, word) in self.words.iter().enumerate() {
+                if col != 0 { result.push(' '); }
+                result.push_str(&word[row].to_string());
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    /// Extract a column of the state, i.e. one of its 4-byte words
+    pub fn column(&self, index: usize) -> [u8; 4] {
+        let word = &self.words[index];
+        [word[0].into(), word[1].into(), word[2].into(), word[3].into()]
+    }
+
+    /// Extract a row of the state, i.e. the same byte of each word
+    pub fn row(&self, index: usize) -> [u8; N_B] {
+        let mut result = [0; N_B];
+        for (byte, word) in result.iter_mut().zip(self.words.iter()) {
+            *byte = word[index].into();
+        }
+        result
+    }
+
+    /// Extract all bytes of the state, in column-major order
+    pub fn bytes(&self) -> [u8; 4*N_B] {
+        let mut result = [0; 4*N_B];
+        for (index, bytes) in result.chunks_mut(4).enumerate() {
+            bytes.copy_from_slice(&self.column(index));
+        }
+        result
+    }
+
     /// SubBytes is a non-linear byte substitution that operates independently
     /// on each byte of the state using a substitution table (S-box)
     pub fn sub_bytes(&mut self) {
@@ -116,6 +159,12 @@ impl State {
         }
     }
 
+    /// XOR a mask into one byte of the state, e.g. to simulate a fault. Bytes
+    /// are indexed in the same order as in input and output blocks.
+    pub fn xor_byte(&mut self, index: usize, mask: u8) {
+        self.words[index / 4][index % 4] += GFByte::from(mask);
+    }
+
     /// This private method applies an S-box to each byte of the state
     fn apply_s_box(&mut self, sb: &SBox) {
         for word in self.words.iter_mut() {
@@ -160,3 +209,48 @@ impl State {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use block_ciphers::aes::state::State;
+    use blocks::BlockError;
+
+    // Check row and column extraction on the FIPS-197 appendix B input
+    #[test]
+    fn rows_and_columns() {
+        let input = [0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d,
+                     0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34];
+        let state = State::from(&input);
+        assert_eq!(state.column(0), [0x32, 0x43, 0xf6, 0xa8]);
+        assert_eq!(state.column(3), [0xe0, 0x37, 0x07, 0x34]);
+        assert_eq!(state.row(0), [0x32, 0x88, 0x31, 0xe0]);
+        assert_eq!(state.row(2), [0xf6, 0x30, 0x98, 0x07]);
+        assert_eq!(state.bytes(), input);
+    }
+
+    // Check that states can be built from slices of the right length only
+    #[test]
+    fn from_slice() {
+        let bytes = (0..16).collect::<Vec<u8>>();
+        let state = State::from_slice(&bytes).unwrap();
+        assert_eq!(state.to_grid_string(),
+                   "00 04 08 0c\n01 05 09 0d\n02 06 0a 0e\n03 07 0b 0f\n");
+        match State::from_slice(&bytes[..15]) {
+            Err(BlockError::WrongLength { expected: 16, got: 15 }) => {},
+            Err(other) => panic!("Unexpected error {:?}", other),
+            Ok(_) => panic!("Unexpected success"),
+        }
+    }
+
+    // Check the grid display against the input of the FIPS-197 appendix B
+    #[test]
+    fn to_grid_string() {
+        let state = State::from(&[0x32, 0x43, 0xf6, 0xa8,
+                                  0x88, 0x5a, 0x30, 0x8d,
+                                  0x31, 0x31, 0x98, 0xa2,
+                                  0xe0, 0x37, 0x07, 0x34]);
+        assert_eq!(state.to_grid_string(),
+                   "32 88 31 e0\n43 5a 31 37\nf6 30 98 07\na8 8d a2 34\n");
+    }
+}
@@ -0,0 +1,67 @@
+//! This module provides a generic implementation of Feistel networks, which
+//! are the basis of many block ciphers such as DES.
+//!
+//! A Feistel network splits its input block into a left and a right half. Each
+//! round maps (L, R) to (R, L ^ F(R, K)), where F is the round function and K
+//! is the round key. Because the halves are swapped once more at the end, the
+//! decryption network is the same as the encryption one, only with the round
+//! keys applied in reverse order. Note that F does not need to be invertible.
+
+
+// Encrypt a 64-bit block using a Feistel network with 32-bit halves. The round
+// function takes a half-block and a round key as parameters.
+pub fn feistel_encrypt<F>(block: u64, round_keys: &[u32], round_fn: F) -> u64
+    where F: Fn(u32, u32) -> u32
+{
+    feistel_network(block, round_keys.iter(), round_fn)
+}
+
+// Decrypt a 64-bit block which was encrypted by feistel_encrypt
+pub fn feistel_decrypt<F>(block: u64, round_keys: &[u32], round_fn: F) -> u64
+    where F: Fn(u32, u32) -> u32
+{
+    feistel_network(block, round_keys.iter().rev(), round_fn)
+}
+
+// Implementation of the Feistel network, for a certain round key ordering
+fn feistel_network<'a, K, F>(block: u64, round_keys: K, round_fn: F) -> u64
+    where K: Iterator<Item=&'a u32>,
+          F: Fn(u32, u32) -> u32
+{
+    // Split the input block into its left (high) and right (low) halves
+    let mut left = (block >> 32) as u32;
+    let mut right = (block & 0xffff_ffff) as u32;
+
+    // Apply the Feistel rounds
+    for key in round_keys {
+        let new_right = left ^ round_fn(right, *key);
+        left = right;
+        right = new_right;
+    }
+
+    // Undo the final swap and recombine the two halves
+    ((right as u64) << 32) | (left as u64)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use block_ciphers::feistel;
+
+    // Even with a non-invertible round function, decryption undoes encryption
+    #[test]
+    fn encrypt_decrypt() {
+        let round_fn = |half: u32, key: u32| {
+            (half.rotate_left(5) & key).wrapping_mul(0x9e37_79b9)
+        };
+        let round_keys = [0x0123_4567, 0x89ab_cdef, 0xdead_beef, 0xcafe_babe,
+                          0x1357_9bdf, 0x2468_ace0, 0x0f0f_0f0f, 0xf0f0_f0f0];
+        let plaintext = 0x0011_2233_4455_6677;
+        let ciphertext = feistel::feistel_encrypt(plaintext,
+                                                  &round_keys,
+                                                  round_fn);
+        assert_ne!(ciphertext, plaintext);
+        assert_eq!(feistel::feistel_decrypt(ciphertext, &round_keys, round_fn),
+                   plaintext);
+    }
+}
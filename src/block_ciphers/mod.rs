@@ -2,4 +2,247 @@
 //! modes of operation.
 
 pub mod aes;
+pub mod feistel;
 pub mod modes;
+pub mod whitened;
+pub mod writers;
+
+use block_ciphers::aes::Key128;
+use block_ciphers::modes::StreamMode;
+use blocks::{self, Block128u8, BlockError};
+use hexfile;
+
+
+// Block cipher modes of operation and related constructions take their keyed
+// block cipher through these traits, which tell the direction in which the
+// cipher is used apart
+pub trait EncryptBlock128 {
+    fn encrypt_block(&self, block: &Block128u8) -> Block128u8;
+}
+//
+pub trait DecryptBlock128 {
+    fn decrypt_block(&self, block: &Block128u8) -> Block128u8;
+}
+//
+/// Closures which combine a block cipher with its key schedule are used as
+/// forward ciphers
+impl<F> EncryptBlock128 for F
+    where F: Fn(&Block128u8) -> Block128u8
+{
+    fn encrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        self(block)
+    }
+}
+
+// Closures are not accepted as inverse ciphers as they are, otherwise the
+// forward cipher could be given to a decryption function by mistake. Inverse
+// cipher closures must instead be explicitly wrapped into this type.
+pub struct InverseCipher128<KIC>
+    where KIC: Fn(&Block128u8) -> Block128u8
+{
+    keyed_inv_cipher: KIC,
+}
+//
+impl<KIC> InverseCipher128<KIC>
+    where KIC: Fn(&Block128u8) -> Block128u8
+{
+    // Mark a closure as an inverse keyed block cipher
+    pub fn new(keyed_inv_cipher: KIC) -> Self {
+        Self { keyed_inv_cipher }
+    }
+}
+//
+/// Wrapped closures are used as inverse ciphers
+impl<KIC> DecryptBlock128 for InverseCipher128<KIC>
+    where KIC: Fn(&Block128u8) -> Block128u8
+{
+    fn decrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        (self.keyed_inv_cipher)(block)
+    }
+}
+//
+/// Inverse ciphers may also be borrowed, e.g. by a CbcPlaintextIter
+impl<KIC> DecryptBlock128 for &KIC
+    where KIC: DecryptBlock128 + ?Sized
+{
+    fn decrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        (**self).decrypt_block(block)
+    }
+}
+
+
+// Generate deterministic pseudo-random bytes from a seed, by running AES-128 in
+// CTR mode over zeroes. This is a simplified DRBG for use in exercises:
+// unlike the real CTR_DRBG from NIST SP 800-90A, it has no reseeding and no
+// backtracking resistance, so it must not be used to generate real keys.
+pub fn ctr_drbg_128u8(seed_key: &Key128,
+                      seed_iv: Block128u8,
+                      out_len: usize) -> Vec<u8> {
+    let keyed_cipher = aes::keyed_cipher_128(seed_key);
+    modes::ctr_keystream_128u8(&keyed_cipher, seed_iv, out_len)
+}
+
+
+// The course provides ciphertexts in hexadecimal form, and expects answers in
+// the same form. These helpers take care of the hex conversions around a
+// block cipher mode of operation.
+pub fn encrypt_hex_128u8<KC, M>(keyed_cipher: &KC,
+                                mode: &M,
+                                init_vector: Block128u8,
+                                plaintext: &[u8]) -> String
+    where KC: EncryptBlock128,
+          M: StreamMode
+{
+    hexfile::to_hex(&mode.encrypt(keyed_cipher, init_vector, plaintext))
+}
+
+/// Possible errors when decrypting a hex-encoded ciphertext
+#[derive(Debug)]
+pub enum HexCipherError {
+    /// The ciphertext or IV could not be loaded as hexadecimal data
+    Hex(hexfile::Error),
+
+    /// The IV is not one block long (its actual length is included)
+    InvalidIvLength(usize),
+
+    /// The ciphertext could not be decrypted by the mode of operation
+    Decryption,
+}
+
+// This is the associated decryption helper
+pub fn decrypt_hex_128u8<KC, KIC, M>(keyed_cipher: &KC,
+                                     keyed_inv_cipher: &KIC,
+                                     mode: &M,
+                                     init_vector: Block128u8,
+                                     ciphertext_hex: &str)
+                                     -> Result<Vec<u8>, HexCipherError>
+    where KC: EncryptBlock128,
+          KIC: DecryptBlock128,
+          M: StreamMode
+{
+    let ciphertext = hexfile::parse_hex(ciphertext_hex)
+                             .map_err(HexCipherError::Hex)?;
+    mode.decrypt(keyed_cipher, keyed_inv_cipher, init_vector, &ciphertext)
+        .ok_or(HexCipherError::Decryption)
+}
+
+// Some exercises provide the IV and the ciphertext as separate hex files. This
+// loads both of them, checks that the IV is one block long, and decrypts.
+pub fn decrypt_with_iv_files<KC, KIC, M>(ciphertext_path: &str,
+                                         iv_path: &str,
+                                         keyed_cipher: &KC,
+                                         keyed_inv_cipher: &KIC,
+                                         mode: &M)
+                                         -> Result<Vec<u8>, HexCipherError>
+    where KC: EncryptBlock128,
+          KIC: DecryptBlock128,
+          M: StreamMode
+{
+    let ciphertext = hexfile::load_bytes(ciphertext_path)
+                             .map_err(HexCipherError::Hex)?;
+    let init_vector = hexfile::load_bytes(iv_path)
+                              .map_err(HexCipherError::Hex)?;
+    let init_vector = match blocks::try_as_block_128u8(&init_vector) {
+        Ok(block) => *block,
+        Err(BlockError::WrongLength { got, .. }) => {
+            return Err(HexCipherError::InvalidIvLength(got));
+        },
+    };
+    mode.decrypt(keyed_cipher, keyed_inv_cipher, init_vector, &ciphertext)
+        .ok_or(HexCipherError::Decryption)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use block_ciphers::{self, aes, HexCipherError};
+    use block_ciphers::modes::{Cbc, Ctr, StreamMode};
+    use hexfile;
+    use test_utils::TempFile;
+
+    #[test]
+    fn ctr_drbg_128u8() {
+        let bytes = block_ciphers::ctr_drbg_128u8(&[0x42; 16], [0x24; 16], 100);
+        assert_eq!(bytes.len(), 100);
+        assert_eq!(bytes,
+                   block_ciphers::ctr_drbg_128u8(&[0x42; 16], [0x24; 16], 100));
+        assert_eq!(&bytes[..40],
+                   &block_ciphers::ctr_drbg_128u8(&[0x42; 16],
+                                                  [0x24; 16],
+                                                  40)[..]);
+        assert_ne!(bytes,
+                   block_ciphers::ctr_drbg_128u8(&[0x43; 16], [0x24; 16], 100));
+        assert_ne!(bytes,
+                   block_ciphers::ctr_drbg_128u8(&[0x42; 16], [0x25; 16], 100));
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; 16];
+        let message = b"Attack at dawn";
+        let ciphertext_hex = block_ciphers::encrypt_hex_128u8(&keyed_cipher,
+                                                              &Cbc,
+                                                              init_vector,
+                                                              message);
+        assert_eq!(hexfile::parse_hex(&ciphertext_hex).unwrap().len(), 16);
+        let decrypt = |ciphertext_hex| {
+            block_ciphers::decrypt_hex_128u8(&keyed_cipher,
+                                             &keyed_inv_cipher,
+                                             &Cbc,
+                                             init_vector,
+                                             ciphertext_hex)
+        };
+        assert_eq!(decrypt(&ciphertext_hex).unwrap(), message);
+        match decrypt("0g") {
+            Err(HexCipherError::Hex(hexfile::Error::InvalidChars)) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match decrypt("00112233") {
+            Err(HexCipherError::Decryption) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let ciphertext_hex = block_ciphers::encrypt_hex_128u8(&keyed_cipher,
+                                                              &Ctr,
+                                                              init_vector,
+                                                              message);
+        assert_eq!(block_ciphers::decrypt_hex_128u8(&keyed_cipher,
+                                                    &keyed_inv_cipher,
+                                                    &Ctr,
+                                                    init_vector,
+                                                    &ciphertext_hex)
+                                 .unwrap(),
+                   message);
+    }
+
+    #[test]
+    fn decrypt_with_iv_files() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; 16];
+        let message = b"Separate IV and ciphertext files";
+        let ciphertext = Cbc.encrypt(&keyed_cipher, init_vector, message);
+        let hex_file = |bytes: &[u8]| {
+            TempFile::new(format!("{}\n", hexfile::to_hex(bytes)).as_bytes())
+        };
+        let ct_file = hex_file(&ciphertext);
+        let iv_file = hex_file(&init_vector);
+        let bad_iv_file = hex_file(&init_vector[..12]);
+        let decrypt = |iv_path| {
+            block_ciphers::decrypt_with_iv_files(ct_file.path(),
+                                                 iv_path,
+                                                 &keyed_cipher,
+                                                 &keyed_inv_cipher,
+                                                 &Cbc)
+        };
+        let decrypted = decrypt(iv_file.path());
+        let bad_iv_result = decrypt(bad_iv_file.path());
+        assert_eq!(decrypted.unwrap(), message);
+        match bad_iv_result {
+            Err(HexCipherError::InvalidIvLength(12)) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
@@ -1,8 +1,84 @@
 //! This module implementes various block cipher modes of operation
 
+use block_ciphers::{DecryptBlock128, EncryptBlock128};
+use block_ciphers::aes::{self, Aes128, Key128};
 use blocks::{self, Block128u8, BLOCK_LEN_128_U8};
+use hash::hmac;
+use hash::sha_256::{Digest, DIGEST_LEN};
 use padding::PaddingScheme;
-use inplace_xor_bytes;
+use padding::pkcs7::{self, PKCS7Padding128u8};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::iter::Peekable;
+use std::slice::Chunks;
+use {inplace_xor_bytes, xor_bulk};
+
+
+// Passing separate keyed_cipher and keyed_inv_cipher closures around makes it
+// easy to give a decryption function the forward cipher by mistake. A keyed
+// cipher object holds both directions, so it can be passed as both the keyed
+// cipher and the keyed inverse cipher, and each mode picks the direction that
+// it needs.
+pub struct KeyedCipher128<KC, KIC>
+    where KC: EncryptBlock128,
+          KIC: DecryptBlock128
+{
+    forward: KC,
+    inverse: KIC,
+}
+//
+impl<KC, KIC> KeyedCipher128<KC, KIC>
+    where KC: EncryptBlock128,
+          KIC: DecryptBlock128
+{
+    // Any pair of forward and inverse keyed block ciphers may be used. Inverse
+    // cipher closures must be wrapped into an InverseCipher128 beforehand.
+    pub fn new(keyed_cipher: KC, keyed_inv_cipher: KIC) -> Self {
+        Self {
+            forward: keyed_cipher,
+            inverse: keyed_inv_cipher,
+        }
+    }
+
+    // Encrypt a single block
+    pub fn forward(&self, block: &Block128u8) -> Block128u8 {
+        self.forward.encrypt_block(block)
+    }
+
+    // Decrypt a single block
+    pub fn inverse(&self, block: &Block128u8) -> Block128u8 {
+        self.inverse.decrypt_block(block)
+    }
+}
+//
+/// Keyed cipher objects use their forward cipher when encrypting
+impl<KC, KIC> EncryptBlock128 for KeyedCipher128<KC, KIC>
+    where KC: EncryptBlock128,
+          KIC: DecryptBlock128
+{
+    fn encrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        self.forward(block)
+    }
+}
+//
+/// Keyed cipher objects use their inverse cipher when decrypting
+impl<KC, KIC> DecryptBlock128 for KeyedCipher128<KC, KIC>
+    where KC: EncryptBlock128,
+          KIC: DecryptBlock128
+{
+    fn decrypt_block(&self, block: &Block128u8) -> Block128u8 {
+        self.inverse(block)
+    }
+}
+
+// Build the AES-128 keyed cipher object associated with some key
+pub fn keyed_aes_128(key: &Key128)
+    -> KeyedCipher128<impl EncryptBlock128, impl DecryptBlock128>
+{
+    KeyedCipher128::new(aes::keyed_cipher_128(key),
+                        aes::keyed_inv_cipher_128(key))
+}
 
 
 // This is an implementation of the Cipher Block Chaining mode of operation for
@@ -10,8 +86,9 @@ use inplace_xor_bytes;
 //
 // Its inputs should be built as follows:
 //
-// * Construct a closure which combines your block cipher of choice with its
-//   key schedule, removing any cipher-specific dependence on a given key type
+// * Construct a closure (or a keyed cipher object) which combines your block
+//   cipher of choice with its key schedule, removing any cipher-specific
+//   dependence on a given key type
 // * Provide an appropriate IV (and remember to transmit it to your recipient)
 // * Pad the input message into a stream of complete blocks using your
 //   padding scheme of choice (e.g. the PKCS#7 one)
@@ -19,15 +96,79 @@ use inplace_xor_bytes;
 pub fn cbc_128u8<'a, KC, PI>(keyed_cipher: &KC,
                              init_vector: Block128u8,
                              padded_input: PI) -> Vec<u8>
-    where KC: Fn(&Block128u8) -> Block128u8,
+    where KC: EncryptBlock128,
           PI: PaddingScheme<'a, Block128u8>
 {
-    // Map the stream of input blocks into a stream of CBC-encrypted blocks
     let mut last_ciphertext = init_vector;
-    let output_iter = padded_input.map(move |mut block| {
-        inplace_xor_bytes(&mut block[..], &last_ciphertext[..]);
-        last_ciphertext = keyed_cipher(&block);
-        last_ciphertext
+    cbc_chain_128u8(keyed_cipher, &mut last_ciphertext, padded_input)
+}
+
+
+// Most of the time, one simply wants to encrypt a message with CBC and PKCS#7
+// padding, which this convenience function does in a single call
+pub fn cbc_encrypt_bytes_128u8<KC>(keyed_cipher: &KC,
+                                   init_vector: Block128u8,
+                                   plaintext: &[u8]) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    cbc_128u8(keyed_cipher, init_vector, PKCS7Padding128u8::new(plaintext))
+}
+
+// Some protocols transmit the plaintext length alongside the ciphertext, so
+// that the recipient can truncate the decrypted output instead of parsing the
+// padding. This variant returns that length along with the ciphertext.
+pub fn cbc_encrypt_bytes_with_len_128u8<KC>(keyed_cipher: &KC,
+                                            init_vector: Block128u8,
+                                            plaintext: &[u8])
+                                            -> (Vec<u8>, usize)
+    where KC: EncryptBlock128
+{
+    (cbc_encrypt_bytes_128u8(keyed_cipher, init_vector, plaintext),
+     plaintext.len())
+}
+
+// When the plaintext is already owned, it can be PKCS#7-padded and encrypted
+// in place, without allocating a separate ciphertext vector
+pub fn cbc_encrypt_in_place_128u8<KC>(keyed_cipher: &KC,
+                                      init_vector: Block128u8,
+                                      data: &mut Vec<u8>)
+    where KC: EncryptBlock128
+{
+    // Extend the plaintext with PKCS#7 padding
+    let pad_len = BLOCK_LEN_128_U8 - data.len() % BLOCK_LEN_128_U8;
+    data.resize(data.len() + pad_len, pad_len as u8);
+
+    // Encrypt each block in place, chaining it with the previous one
+    let mut prev_cipher_block = init_vector;
+    for chunk in data.chunks_mut(BLOCK_LEN_128_U8) {
+        let block = blocks::as_mut_block_128u8(chunk);
+        let chained_block = blocks::xor_block_128u8(block, &prev_cipher_block);
+        *block = keyed_cipher.encrypt_block(&chained_block);
+        prev_cipher_block = *block;
+    }
+}
+
+
+// This lower-level version of CBC encryption allows a message to be encrypted
+// in several steps (e.g. one per network packet). The chaining state is the
+// previous ciphertext block, which should be initialized with the IV before
+// the first call and is updated to the last ciphertext block on return.
+//
+// Since the message is only padded at the end, the input blocks of all calls
+// but the last one should be unpadded, whereas the last call should be fed
+// with the output of a padding scheme.
+//
+pub fn cbc_chain_128u8<KC, I>(keyed_cipher: &KC,
+                              prev_cipher_block: &mut Block128u8,
+                              input_blocks: I) -> Vec<u8>
+    where KC: EncryptBlock128,
+          I: Iterator<Item=Block128u8>
+{
+    // Map the stream of input blocks into a stream of CBC-encrypted blocks
+    let output_iter = input_blocks.map(|block| {
+        let chained_block = blocks::xor_block_128u8(&block, prev_cipher_block);
+        *prev_cipher_block = keyed_cipher.encrypt_block(&chained_block);
+        *prev_cipher_block
     });
 
     // Collect the output blocks into an output ciphertext
@@ -40,69 +181,1357 @@ pub fn cbc_128u8<'a, KC, PI>(keyed_cipher: &KC,
 // cipher and that the input is a message instead of a block iterator.
 //
 // The input must be valid CBC-encoded ciphertext, so its size should be a
-// multiple of the block size. Otherwise, decryption will return None.
+// multiple of the block size and its plaintext should carry valid PKCS#7
+// padding. Otherwise, decryption will return None.
 //
 pub fn inv_cbc_128u8<KIC>(keyed_inv_cipher: &KIC,
                           init_vector: Block128u8,
                           input: &[u8]) -> Option<Vec<u8>>
-    where KIC: Fn(&Block128u8) -> Block128u8
+    where KIC: DecryptBlock128
+{
+    // Decrypt the input, then discard the padding and output the final message
+    let mut output_vec = inv_cbc_128u8_no_pad(keyed_inv_cipher,
+                                              init_vector,
+                                              input)?;
+    pkcs7::unpad_vec(&mut output_vec).ok()?;
+    Some(output_vec)
+}
+
+// Many formats transmit the IV as the first block of the ciphertext. This
+// splits such data into the IV and the ciphertext body, returning None if it is
+// too short to contain an IV.
+pub fn split_iv_128u8(data: &[u8]) -> Option<(&Block128u8, &[u8])> {
+    if data.len() < BLOCK_LEN_128_U8 { return None; }
+    let (init_vector, body) = data.split_at(BLOCK_LEN_128_U8);
+    Some((blocks::as_block_128u8(init_vector), body))
+}
+
+// CBC decryption of a ciphertext which starts with its IV
+pub fn inv_cbc_128u8_with_iv<KIC>(keyed_inv_cipher: &KIC,
+                                  input: &[u8]) -> Option<Vec<u8>>
+    where KIC: DecryptBlock128
+{
+    let (init_vector, body) = split_iv_128u8(input)?;
+    inv_cbc_128u8(keyed_inv_cipher, *init_vector, body)
+}
+
+
+// Some protocols guarantee that the plaintext is block-aligned, and do not use
+// padding. For these, CBC may be run over exactly the provided blocks. It is
+// then the caller's responsibility to transmit the message length if needed.
+pub fn cbc_128u8_no_pad<KC>(keyed_cipher: &KC,
+                            init_vector: Block128u8,
+                            blocks: &[Block128u8]) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    let mut last_ciphertext = init_vector;
+    cbc_chain_128u8(keyed_cipher, &mut last_ciphertext, blocks.iter().cloned())
+}
+
+// This is the associated decryption primitive, which does not strip padding.
+// The input must still be a multiple of the block size, or None is returned.
+pub fn inv_cbc_128u8_no_pad<KIC>(keyed_inv_cipher: &KIC,
+                                 init_vector: Block128u8,
+                                 input: &[u8]) -> Option<Vec<u8>>
+    where KIC: DecryptBlock128
 {
     // Make sure that the input is a reasonable sequence of blocks, and produce
     // an iterator of blocks out of it
     let input_len = input.len();
     if input_len % BLOCK_LEN_128_U8 != 0 { return None; }
     let input_iter = input.chunks(BLOCK_LEN_128_U8)
-                          .map(|slice| blocks::as_block_128u8(slice));
+                          .map(blocks::as_block_128u8);
 
     // Map the stream of input blocks into a stream of CBC-decrypted blocks
     let mut last_ciphertext = &init_vector;
     let output_iter = input_iter.map(move |ciphertext_block| {
-        let mut result = keyed_inv_cipher(ciphertext_block);
+        let mut result = keyed_inv_cipher.decrypt_block(ciphertext_block);
         inplace_xor_bytes(&mut result[..], &last_ciphertext[..]);
         last_ciphertext = ciphertext_block;
         result
     });
 
     // Collect the output blocks into an output message
-    let mut output_vec = blocks::into_vec_128u8(output_iter);
+    Some(blocks::into_vec_128u8(output_iter))
+}
 
-    // Discard the padding and output the final message
-    let padding_bytes = output_vec[input_len-1];
-    output_vec.truncate(input_len - padding_bytes as usize);
-    Some(output_vec)
+
+// This lazy version of CBC decryption yields the plaintext one block at a time,
+// as a vector of bytes. Since only the last block carries padding, it looks one
+// ciphertext block ahead to tell when the last block is reached, and then
+// strips the padding from it, yielding an error if the padding is invalid.
+pub struct CbcPlaintextIter<'a, KIC>
+    where KIC: DecryptBlock128
+{
+    keyed_inv_cipher: KIC,
+    prev_cipher_block: Block128u8,
+    input_blocks: Peekable<Chunks<'a, u8>>,
+    finished: bool,
+}
+//
+impl<'a, KIC> CbcPlaintextIter<'a, KIC>
+    where KIC: DecryptBlock128
+{
+    // Start decrypting a ciphertext, which must be a multiple of the block
+    // size or None is returned
+    pub fn new(keyed_inv_cipher: KIC,
+               init_vector: Block128u8,
+               input: &'a [u8]) -> Option<Self> {
+        if input.len() % BLOCK_LEN_128_U8 != 0 { return None; }
+        Some(Self {
+            keyed_inv_cipher,
+            prev_cipher_block: init_vector,
+            input_blocks: input.chunks(BLOCK_LEN_128_U8).peekable(),
+            finished: false,
+        })
+    }
+}
+//
+impl<'a, KIC> Iterator for CbcPlaintextIter<'a, KIC>
+    where KIC: DecryptBlock128
+{
+    type Item = Result<Vec<u8>, pkcs7::PadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished { return None; }
+
+        // An empty ciphertext cannot contain any padding
+        let cipher_block = match self.input_blocks.next() {
+            Some(chunk) => blocks::as_block_128u8(chunk),
+            None => {
+                self.finished = true;
+                return Some(Err(pkcs7::PadError::Empty));
+            }
+        };
+
+        // Decrypt the current block
+        let mut plain_block = self.keyed_inv_cipher.decrypt_block(cipher_block);
+        inplace_xor_bytes(&mut plain_block[..], &self.prev_cipher_block[..]);
+        self.prev_cipher_block = *cipher_block;
+        let mut output = plain_block.to_vec();
+
+        // Strip the padding if this was the last block
+        if self.input_blocks.peek().is_none() {
+            self.finished = true;
+            return Some(pkcs7::unpad_vec(&mut output).map(|()| output));
+        }
+        Some(Ok(output))
+    }
+}
+
+// Extract the last block of a block-aligned ciphertext, e.g. to use it as a
+// CBC-MAC tag or as the IV of the next message. Returns None if the ciphertext
+// is empty or not block-aligned.
+pub fn last_block_128u8(ciphertext: &[u8]) -> Option<&Block128u8> {
+    let len = ciphertext.len();
+    if len == 0 || len % BLOCK_LEN_128_U8 != 0 { return None; }
+    Some(blocks::as_block_128u8(&ciphertext[len-BLOCK_LEN_128_U8..]))
 }
 
 
+// The Electronic Code Book mode encrypts each block independently. It leaks
+// which plaintext blocks are equal, and should thus only be used for study.
+// Like CBC, it needs the input to be padded, which this function does using
+// PKCS#7 padding.
+pub fn ecb_encrypt_bytes_128u8<KC>(keyed_cipher: &KC,
+                                   plaintext: &[u8]) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    let output_iter = PKCS7Padding128u8::new(plaintext)
+                          .map(|block| keyed_cipher.encrypt_block(&block));
+    blocks::into_vec_128u8(output_iter)
+}
+
+/// Possible errors when decrypting a padded block cipher mode
+#[derive(Debug, Eq, PartialEq)]
+pub enum ModeError {
+    /// The ciphertext is not a multiple of the block size
+    UnalignedInput,
+
+    /// The decrypted message does not carry valid padding
+    Padding(pkcs7::PadError),
+}
+
+// This is the associated decryption primitive, which strips the padding
+pub fn ecb_decrypt_bytes_128u8<KIC>(keyed_inv_cipher: &KIC,
+                                    ciphertext: &[u8])
+                                    -> Result<Vec<u8>, ModeError>
+    where KIC: DecryptBlock128
+{
+    if ciphertext.len() % BLOCK_LEN_128_U8 != 0 {
+        return Err(ModeError::UnalignedInput);
+    }
+    let output_iter = ciphertext.chunks(BLOCK_LEN_128_U8)
+                                .map(blocks::as_block_128u8)
+                                .map(|b| keyed_inv_cipher.decrypt_block(b));
+    let mut output_vec = blocks::into_vec_128u8(output_iter);
+    pkcs7::unpad_vec(&mut output_vec).map_err(ModeError::Padding)?;
+    Ok(output_vec)
+}
+
 // This is the encryption/decryption primitive associated with the CTR cipher
 // mode, which is its own inverse and requires no input padding.
 pub fn ctr_128u8<KC>(keyed_cipher: &KC,
                      init_vector: Block128u8,
                      input: &[u8]) -> Vec<u8>
-    where KC: Fn(&Block128u8) -> Block128u8
+    where KC: EncryptBlock128
+{
+    ctr_128u8_endian(keyed_cipher, init_vector, input, CounterEndian::Big)
+}
+
+// When the initial counter is a number, it is more convenient to provide it
+// as such. It is converted to a big-endian IV.
+pub fn ctr_128u8_from_u128<KC>(keyed_cipher: &KC,
+                               counter: u128,
+                               input: &[u8]) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    ctr_128u8(keyed_cipher, counter.to_be_bytes(), input)
+}
+
+// The CTR keystream, which the input is XORed with, may also be produced on
+// its own (e.g. for teaching stream ciphers or building other constructions).
+// This is simply CTR encryption of zeroes.
+pub fn ctr_keystream_128u8<KC>(keyed_cipher: &KC,
+                               init_vector: Block128u8,
+                               len: usize) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    ctr_128u8(keyed_cipher, init_vector, &vec![0; len])
+}
+
+
+// The CTR counter is usually incremented as a big-endian integer, but some
+// protocols increment it as a little-endian integer instead
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CounterEndian {
+    Big,
+    Little,
+}
+
+// This version of CTR lets one pick the endianness of the counter
+pub fn ctr_128u8_endian<KC>(keyed_cipher: &KC,
+                            init_vector: Block128u8,
+                            input: &[u8],
+                            endian: CounterEndian) -> Vec<u8>
+    where KC: EncryptBlock128
 {
     // CTR is based on maintaining an internal counter, starting at the IV
     let mut counter = init_vector;
     let mut next_counter = move || -> Block128u8 {
         let old_counter = counter;
-        let mut index = BLOCK_LEN_128_U8 - 1;
-        loop {
-            let (new_value, overflow) = counter[index].overflowing_add(1);
-            counter[index] = new_value;
-            if !overflow { break; }
-            index = if index != 0 { index-1 } else { BLOCK_LEN_128_U8-1 };
-        }
+        increment_counter(&mut counter, endian);
         old_counter
     };
 
     // We build our output by XORing the input bytes with the encrypted counter,
     // which acts as a one-time pad, operating as a stream cipher
-    let mut output = Vec::with_capacity(input.len());
-    for input in input.chunks(BLOCK_LEN_128_U8) {
+    let mut output = input.to_vec();
+    for output_chunk in output.chunks_mut(BLOCK_LEN_128_U8) {
         let counter = next_counter();
-        let one_time_pad = keyed_cipher(&counter);
-        for (input_byte, otp_byte) in input.iter().zip(one_time_pad.iter()) {
-            output.push(input_byte ^ otp_byte);
+        let one_time_pad = keyed_cipher.encrypt_block(&counter);
+        xor_bulk(output_chunk, &one_time_pad);
+    }
+    output
+}
+
+// Increment a CTR counter, wrapping around on overflow. The least significant
+// byte comes last in big-endian order, and first in little-endian order.
+fn increment_counter(counter: &mut Block128u8, endian: CounterEndian) {
+    for step in 0..BLOCK_LEN_128_U8 {
+        let index = match endian {
+            CounterEndian::Big => BLOCK_LEN_128_U8 - 1 - step,
+            CounterEndian::Little => step,
+        };
+        let (new_value, overflow) = counter[index].overflowing_add(1);
+        counter[index] = new_value;
+        if !overflow { break; }
+    }
+}
+
+
+// Some formats split the CTR input block into a fixed 64-bit nonce, in the
+// first half, and a 64-bit big-endian counter, in the second half. With this
+// layout, the counter wraps around to zero after 2^64 blocks without carrying
+// into the nonce (but you should never encrypt that much data with one key).
+pub fn ctr_128u8_split<KC>(keyed_cipher: &KC,
+                           nonce: &[u8; 8],
+                           initial_counter: u64,
+                           input: &[u8]) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    let mut output = input.to_vec();
+    let mut counter = initial_counter;
+    let mut counter_block = [0; BLOCK_LEN_128_U8];
+    counter_block[..8].copy_from_slice(nonce);
+    for output_chunk in output.chunks_mut(BLOCK_LEN_128_U8) {
+        counter_block[8..].copy_from_slice(&counter.to_be_bytes());
+        xor_bulk(output_chunk, &keyed_cipher.encrypt_block(&counter_block));
+        counter = counter.wrapping_add(1);
+    }
+    output
+}
+
+
+// ctr_128u8 starts over from the IV on every call, so a message which arrives
+// in chunks cannot be encrypted chunk by chunk with it. This stateful CTR
+// encryptor remembers the current counter and the unused part of the last
+// keystream block, so that the keystream continues across calls even when the
+// chunks are not block-aligned.
+pub struct CtrState<KC>
+    where KC: EncryptBlock128
+{
+    keyed_cipher: KC,
+    counter: Block128u8,
+    keystream: Block128u8,
+    keystream_pos: usize,
+}
+//
+impl<KC> CtrState<KC>
+    where KC: EncryptBlock128
+{
+    // Start encrypting a message, with the counter starting at the IV
+    pub fn new(keyed_cipher: KC, init_vector: Block128u8) -> Self {
+        Self {
+            keyed_cipher,
+            counter: init_vector,
+            keystream: [0; BLOCK_LEN_128_U8],
+            keystream_pos: BLOCK_LEN_128_U8,
+        }
+    }
+
+    // Encrypt or decrypt the next chunk of the message
+    pub fn process(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut output = data.to_vec();
+        for byte in output.iter_mut() {
+            // Generate a new keystream block when the previous one is used up,
+            // then increment the counter as a big-endian integer
+            if self.keystream_pos == BLOCK_LEN_128_U8 {
+                self.keystream = self.keyed_cipher.encrypt_block(&self.counter);
+                self.keystream_pos = 0;
+                increment_counter(&mut self.counter, CounterEndian::Big);
+            }
+            *byte ^= self.keystream[self.keystream_pos];
+            self.keystream_pos += 1;
+        }
+        output
+    }
+}
+
+
+// Reusing an IV in CTR mode reuses the keystream, which leaks the XOR of the
+// two plaintexts. As a guard rail, this stateful CTR encryptor remembers the
+// IVs that it has used, and refuses to encrypt with any of them again. Note
+// that it does not detect overlapping counter ranges between different IVs.
+pub struct CtrSession<KC>
+    where KC: EncryptBlock128
+{
+    keyed_cipher: KC,
+    used_ivs: HashSet<Block128u8>,
+}
+//
+impl<KC> CtrSession<KC>
+    where KC: EncryptBlock128
+{
+    // Start a new session, with no IV used yet
+    pub fn new(keyed_cipher: KC) -> Self {
+        Self {
+            keyed_cipher,
+            used_ivs: HashSet::new(),
+        }
+    }
+
+    // Encrypt a message with CTR mode, unless the IV was used before
+    pub fn encrypt(&mut self,
+                   init_vector: Block128u8,
+                   input: &[u8]) -> Result<Vec<u8>, IvReuseError> {
+        if !self.used_ivs.insert(init_vector) {
+            return Err(IvReuseError(init_vector));
         }
+        Ok(ctr_128u8(&self.keyed_cipher, init_vector, input))
     }
+}
+
+/// Error returned by CtrSession when an IV is used twice (the IV is included)
+#[derive(Debug, Eq, PartialEq)]
+pub struct IvReuseError(pub Block128u8);
+
+
+// This is the encryption/decryption primitive associated with the OFB cipher
+// mode. Like CTR, it turns the block cipher into a stream cipher, but the
+// keystream is generated by repeatedly encrypting the IV.
+pub fn ofb_128u8<KC>(keyed_cipher: &KC,
+                     init_vector: Block128u8,
+                     input: &[u8]) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    let mut output = input.to_vec();
+    let keystream = ofb_keystream_128u8(keyed_cipher, init_vector, input.len());
+    xor_bulk(&mut output, &keystream);
     output
 }
+
+// The OFB keystream may also be produced on its own. Unlike the CTR one, each
+// keystream block is fed back into the cipher to produce the next one, so it
+// never depends on the plaintext or ciphertext, but cannot be computed at a
+// random position without computing all previous blocks.
+pub fn ofb_keystream_128u8<KC>(keyed_cipher: &KC,
+                               init_vector: Block128u8,
+                               len: usize) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    let mut keystream = Vec::with_capacity(len + BLOCK_LEN_128_U8);
+    let mut keystream_block = init_vector;
+    while keystream.len() < len {
+        keystream_block = keyed_cipher.encrypt_block(&keystream_block);
+        keystream.extend_from_slice(&keystream_block);
+    }
+    keystream.truncate(len);
+    keystream
+}
+
+
+// This is the encryption primitive associated with the (full-block) CFB cipher
+// mode, where the keystream is generated by encrypting the previous ciphertext
+// block. No padding is needed, as the last block may be truncated.
+pub fn cfb_128u8<KC>(keyed_cipher: &KC,
+                     init_vector: Block128u8,
+                     input: &[u8]) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    let mut output = input.to_vec();
+    let mut last_ciphertext = init_vector;
+    for output_chunk in output.chunks_mut(BLOCK_LEN_128_U8) {
+        xor_bulk(output_chunk, &keyed_cipher.encrypt_block(&last_ciphertext));
+        last_ciphertext[..output_chunk.len()].copy_from_slice(output_chunk);
+    }
+    output
+}
+
+// CFB decryption also uses the forward cipher, but chains on the input
+pub fn inv_cfb_128u8<KC>(keyed_cipher: &KC,
+                         init_vector: Block128u8,
+                         input: &[u8]) -> Vec<u8>
+    where KC: EncryptBlock128
+{
+    let mut output = input.to_vec();
+    let mut last_ciphertext = init_vector;
+    let input_chunks = input.chunks(BLOCK_LEN_128_U8);
+    for (output_chunk, input_chunk) in output.chunks_mut(BLOCK_LEN_128_U8)
+                                             .zip(input_chunks) {
+        xor_bulk(output_chunk, &keyed_cipher.encrypt_block(&last_ciphertext));
+        last_ciphertext[..input_chunk.len()].copy_from_slice(input_chunk);
+    }
+    output
+}
+
+
+// The XEX (XOR-Encrypt-XOR) construction turns a block cipher into a tweakable
+// block cipher, by XORing a tweak into the block before and after encryption.
+// It is the core of the XTS disk encryption mode, where the tweak is derived
+// from the sector number and the position of the block within the sector.
+pub fn xex_128u8<KC>(keyed_cipher: &KC,
+                     tweak: &Block128u8,
+                     block: &Block128u8) -> Block128u8
+    where KC: EncryptBlock128
+{
+    let encrypted =
+        keyed_cipher.encrypt_block(&blocks::xor_block_128u8(block, tweak));
+    blocks::xor_block_128u8(&encrypted, tweak)
+}
+
+// XEX decryption uses the same tweak with the inverse cipher
+pub fn inv_xex_128u8<KIC>(keyed_inv_cipher: &KIC,
+                          tweak: &Block128u8,
+                          block: &Block128u8) -> Block128u8
+    where KIC: DecryptBlock128
+{
+    let decrypted =
+        keyed_inv_cipher.decrypt_block(&blocks::xor_block_128u8(block, tweak));
+    blocks::xor_block_128u8(&decrypted, tweak)
+}
+
+
+// Encryption alone does not protect a message against tampering. The
+// recommended way to add integrity by composition is encrypt-then-MAC: the
+// message is CBC-encrypted, then an HMAC-SHA256 tag of the IV and ciphertext
+// is computed with an independent key. The ciphertext and tag are returned.
+pub fn cbc_then_hmac_128u8<KC>(keyed_cipher: &KC,
+                               mac_key: &[u8],
+                               init_vector: Block128u8,
+                               plaintext: &[u8]) -> (Vec<u8>, Digest)
+    where KC: EncryptBlock128
+{
+    let ciphertext = cbc_encrypt_bytes_128u8(keyed_cipher,
+                                             init_vector,
+                                             plaintext);
+    let tag = hmac::hmac_sha256(mac_key, &mac_input(&init_vector, &ciphertext));
+    (ciphertext, tag)
+}
+
+/// Possible errors when decrypting an encrypt-then-MAC message
+#[derive(Debug, Eq, PartialEq)]
+pub enum AuthError {
+    /// The authentication tag does not match the IV and ciphertext
+    InvalidTag,
+
+    /// The tag is valid, but the decrypted message is not properly padded
+    InvalidPadding,
+}
+
+// This is the associated decryption primitive. The tag is checked in constant
+// time before anything is decrypted, so that tampered ciphertexts never reach
+// the block cipher or the padding check (which could act as an oracle).
+pub fn inv_cbc_then_hmac_128u8<KIC>(keyed_inv_cipher: &KIC,
+                                    mac_key: &[u8],
+                                    init_vector: Block128u8,
+                                    ciphertext: &[u8],
+                                    tag: &[u8]) -> Result<Vec<u8>, AuthError>
+    where KIC: DecryptBlock128
+{
+    let mac_input = mac_input(&init_vector, ciphertext);
+    if !hmac::verify_sha256(mac_key, &mac_input, tag) {
+        return Err(AuthError::InvalidTag);
+    }
+    inv_cbc_128u8(keyed_inv_cipher, init_vector, ciphertext)
+        .ok_or(AuthError::InvalidPadding)
+}
+
+// The MAC covers the IV followed by the ciphertext
+fn mac_input(init_vector: &Block128u8, ciphertext: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(BLOCK_LEN_128_U8 + ciphertext.len());
+    result.extend_from_slice(init_vector);
+    result.extend_from_slice(ciphertext);
+    result
+}
+
+
+// This is the full recommended construction, in a single call: a random IV is
+// generated, the message is encrypted with AES-128-CBC, and the output is the
+// IV, followed by the ciphertext, followed by an HMAC-SHA256 tag of both.
+//
+// The IV is read from /dev/urandom, so this only works on Unix-like systems.
+// An error is returned if the random number generator cannot be read.
+pub fn seal_cbc_hmac(enc_key: &Key128,
+                     mac_key: &[u8],
+                     plaintext: &[u8]) -> io::Result<Vec<u8>> {
+    let init_vector = random_iv()?;
+    let keyed_cipher = aes::keyed_cipher_128(enc_key);
+    let (ciphertext, tag) = cbc_then_hmac_128u8(&keyed_cipher,
+                                                mac_key,
+                                                init_vector,
+                                                plaintext);
+    let mut result = mac_input(&init_vector, &ciphertext);
+    result.extend_from_slice(&tag);
+    Ok(result)
+}
+
+// This is the associated decryption primitive, which checks the tag before
+// decrypting. Sealed messages which are too short to contain an IV and a tag
+// are rejected as having an invalid tag.
+pub fn open_cbc_hmac(enc_key: &Key128,
+                     mac_key: &[u8],
+                     sealed: &[u8]) -> Result<Vec<u8>, AuthError> {
+    if sealed.len() < BLOCK_LEN_128_U8 + DIGEST_LEN {
+        return Err(AuthError::InvalidTag);
+    }
+    let (init_vector, rest) = split_iv_128u8(sealed)
+                                  .ok_or(AuthError::InvalidTag)?;
+    let (ciphertext, tag) = rest.split_at(rest.len() - DIGEST_LEN);
+    let keyed_inv_cipher = aes::keyed_inv_cipher_128(enc_key);
+    inv_cbc_then_hmac_128u8(&keyed_inv_cipher,
+                            mac_key,
+                            *init_vector,
+                            ciphertext,
+                            tag)
+}
+
+// Generate a random IV using the random number generator of Unix-like systems
+fn random_iv() -> io::Result<Block128u8> {
+    let mut result = [0; BLOCK_LEN_128_U8];
+    File::open("/dev/urandom")?.read_exact(&mut result)?;
+    Ok(result)
+}
+
+
+// Several independent messages may be encrypted with a mode of operation, each
+// with its own IV. The keyed cipher (e.g. an aes::Aes128, or a closure
+// returned by aes::keyed_cipher_128) is shared by all messages, so that the
+// key schedule is only computed once.
+pub fn encrypt_messages_128u8<KC, M>(keyed_cipher: &KC,
+                                     mode: &M,
+                                     messages: &[Vec<u8>],
+                                     ivs: &[Block128u8]) -> Vec<Vec<u8>>
+    where KC: EncryptBlock128,
+          M: StreamMode
+{
+    assert_eq!(messages.len(), ivs.len(), "Each message needs an IV");
+    messages.iter()
+            .zip(ivs.iter())
+            .map(|(message, iv)| mode.encrypt(keyed_cipher, *iv, message))
+            .collect()
+}
+
+
+// The block cipher modes of operation implemented by this module
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlockMode {
+    Ecb,
+    Cbc,
+    Ctr,
+    Ofb,
+    Cfb,
+}
+
+// Tell whether a ciphertext length is plausible for a given mode, in order to
+// catch mismatched mode selection before attempting decryption. ECB and CBC
+// produce at least one full block (due to padding), whereas the stream-like
+// CTR, OFB and CFB modes may produce any length.
+pub fn validate_ciphertext_len(len: usize, mode: BlockMode) -> bool {
+    match mode {
+        BlockMode::Ecb | BlockMode::Cbc => {
+            len != 0 && len % BLOCK_LEN_128_U8 == 0
+        },
+        BlockMode::Ctr | BlockMode::Ofb | BlockMode::Cfb => true,
+    }
+}
+
+// The free functions above have signatures which differ depending on whether
+// a mode needs padding, and on whether it uses the inverse cipher. This trait
+// provides a uniform interface to all modes, taking care of padding if needed.
+//
+// Decryption is given both the forward and the inverse cipher, since modes
+// differ in which one they need. It fails if the input is malformed.
+//
+pub trait StreamMode {
+    fn encrypt<KC>(&self,
+                   keyed_cipher: &KC,
+                   init_vector: Block128u8,
+                   data: &[u8]) -> Vec<u8>
+        where KC: EncryptBlock128;
+
+    fn decrypt<KC, KIC>(&self,
+                        keyed_cipher: &KC,
+                        keyed_inv_cipher: &KIC,
+                        init_vector: Block128u8,
+                        data: &[u8]) -> Option<Vec<u8>>
+        where KC: EncryptBlock128,
+              KIC: DecryptBlock128;
+}
+
+// CBC mode, with PKCS#7 padding
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbc;
+//
+impl StreamMode for Cbc {
+    fn encrypt<KC>(&self,
+                   keyed_cipher: &KC,
+                   init_vector: Block128u8,
+                   data: &[u8]) -> Vec<u8>
+        where KC: EncryptBlock128
+    {
+        cbc_encrypt_bytes_128u8(keyed_cipher, init_vector, data)
+    }
+
+    fn decrypt<KC, KIC>(&self,
+                        _keyed_cipher: &KC,
+                        keyed_inv_cipher: &KIC,
+                        init_vector: Block128u8,
+                        data: &[u8]) -> Option<Vec<u8>>
+        where KC: EncryptBlock128,
+              KIC: DecryptBlock128
+    {
+        inv_cbc_128u8(keyed_inv_cipher, init_vector, data)
+    }
+}
+
+// CTR mode, with a big-endian counter
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ctr;
+//
+impl StreamMode for Ctr {
+    fn encrypt<KC>(&self,
+                   keyed_cipher: &KC,
+                   init_vector: Block128u8,
+                   data: &[u8]) -> Vec<u8>
+        where KC: EncryptBlock128
+    {
+        ctr_128u8(keyed_cipher, init_vector, data)
+    }
+
+    fn decrypt<KC, KIC>(&self,
+                        keyed_cipher: &KC,
+                        _keyed_inv_cipher: &KIC,
+                        init_vector: Block128u8,
+                        data: &[u8]) -> Option<Vec<u8>>
+        where KC: EncryptBlock128,
+              KIC: DecryptBlock128
+    {
+        Some(ctr_128u8(keyed_cipher, init_vector, data))
+    }
+}
+
+// OFB mode
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ofb;
+//
+impl StreamMode for Ofb {
+    fn encrypt<KC>(&self,
+                   keyed_cipher: &KC,
+                   init_vector: Block128u8,
+                   data: &[u8]) -> Vec<u8>
+        where KC: EncryptBlock128
+    {
+        ofb_128u8(keyed_cipher, init_vector, data)
+    }
+
+    fn decrypt<KC, KIC>(&self,
+                        keyed_cipher: &KC,
+                        _keyed_inv_cipher: &KIC,
+                        init_vector: Block128u8,
+                        data: &[u8]) -> Option<Vec<u8>>
+        where KC: EncryptBlock128,
+              KIC: DecryptBlock128
+    {
+        Some(ofb_128u8(keyed_cipher, init_vector, data))
+    }
+}
+
+// Full-block CFB mode
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cfb;
+//
+impl StreamMode for Cfb {
+    fn encrypt<KC>(&self,
+                   keyed_cipher: &KC,
+                   init_vector: Block128u8,
+                   data: &[u8]) -> Vec<u8>
+        where KC: EncryptBlock128
+    {
+        cfb_128u8(keyed_cipher, init_vector, data)
+    }
+
+    fn decrypt<KC, KIC>(&self,
+                        keyed_cipher: &KC,
+                        _keyed_inv_cipher: &KIC,
+                        init_vector: Block128u8,
+                        data: &[u8]) -> Option<Vec<u8>>
+        where KC: EncryptBlock128,
+              KIC: DecryptBlock128
+    {
+        Some(inv_cfb_128u8(keyed_cipher, init_vector, data))
+    }
+}
+
+
+// An AES-128 schedule object can encrypt several independent messages, each
+// with its own IV, using any of the modes above. The key schedule computed by
+// Aes128::new is shared by all messages. ECB ignores the IVs, but they must
+// still be provided, one per message.
+impl Aes128 {
+    pub fn encrypt_messages(&self,
+                            messages: &[Vec<u8>],
+                            mode: BlockMode,
+                            ivs: &[Block128u8]) -> Vec<Vec<u8>> {
+        assert_eq!(messages.len(), ivs.len(), "Each message needs an IV");
+        match mode {
+            BlockMode::Ecb => {
+                messages.iter()
+                        .map(|message| ecb_encrypt_bytes_128u8(self, message))
+                        .collect()
+            },
+            BlockMode::Cbc => encrypt_messages_128u8(self, &Cbc, messages, ivs),
+            BlockMode::Ctr => encrypt_messages_128u8(self, &Ctr, messages, ivs),
+            BlockMode::Ofb => encrypt_messages_128u8(self, &Ofb, messages, ivs),
+            BlockMode::Cfb => encrypt_messages_128u8(self, &Cfb, messages, ivs),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use block_ciphers::aes;
+    use block_ciphers::modes::{self, AuthError, BlockMode, Cbc,
+                               CbcPlaintextIter, Cfb, CounterEndian, Ctr,
+                               CtrSession, CtrState, IvReuseError, ModeError,
+                               Ofb, StreamMode};
+    use padding::pkcs7::PadError;
+    use hexfile;
+    use blocks::{self, Block128u8, BLOCK_LEN_128_U8};
+    use padding::PaddingScheme;
+    use padding::pkcs7::PKCS7Padding128u8;
+    use std::time::{Duration, Instant};
+
+    // Check that a keyed cipher object picks the right cipher direction, in
+    // agreement with the separate keyed cipher closures
+    #[test]
+    fn keyed_cipher_object() {
+        let key = [0x42; 16];
+        let cipher = modes::keyed_aes_128(&key);
+        let keyed_cipher = aes::keyed_cipher_128(&key);
+        let block = [0x24; BLOCK_LEN_128_U8];
+        let encrypted = cipher.forward(&block);
+        assert_eq!(encrypted, keyed_cipher(&block));
+        assert_eq!(cipher.inverse(&encrypted), block);
+
+        let message = b"Keyed cipher objects know their direction";
+        let ciphertext = modes::cbc_encrypt_bytes_128u8(&cipher,
+                                                        block,
+                                                        message);
+        assert_eq!(ciphertext,
+                   modes::cbc_encrypt_bytes_128u8(&keyed_cipher,
+                                                  block,
+                                                  message));
+        assert_eq!(modes::inv_cbc_128u8(&cipher, block, &ciphertext),
+                   Some(message.to_vec()));
+
+        // AES-128 schedule objects may be used in both directions as well
+        let aes_128 = aes::Aes128::new(&key);
+        assert_eq!(modes::cbc_encrypt_bytes_128u8(&aes_128, block, message),
+                   ciphertext);
+        assert_eq!(modes::inv_cbc_128u8(&aes_128, block, &ciphertext),
+                   Some(message.to_vec()));
+
+        let ciphertext = modes::ecb_encrypt_bytes_128u8(&cipher, message);
+        assert_eq!(modes::ecb_decrypt_bytes_128u8(&cipher, &ciphertext),
+                   Ok(message.to_vec()));
+    }
+
+    // Check that XEX round-trips, and that the tweak changes the ciphertext
+    #[test]
+    fn xex_round_trip() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let block = [0x24; BLOCK_LEN_128_U8];
+        let tweak_1 = [0x01; BLOCK_LEN_128_U8];
+        let tweak_2 = [0x02; BLOCK_LEN_128_U8];
+        let ciphertext = modes::xex_128u8(&keyed_cipher, &tweak_1, &block);
+        assert_ne!(ciphertext,
+                   modes::xex_128u8(&keyed_cipher, &tweak_2, &block));
+        assert_eq!(modes::inv_xex_128u8(&keyed_inv_cipher,
+                                        &tweak_1,
+                                        &ciphertext),
+                   block);
+    }
+
+    // Check that the transmitted length can be used instead of the padding
+    #[test]
+    fn cbc_bytes_with_len() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        for len in [0, 5, 16, 33].iter().cloned() {
+            let message = (0..len).collect::<Vec<u8>>();
+            let (ciphertext, message_len) =
+                modes::cbc_encrypt_bytes_with_len_128u8(&keyed_cipher,
+                                                        init_vector,
+                                                        &message);
+            assert_eq!(message_len, len as usize);
+            let mut decrypted =
+                modes::inv_cbc_128u8_no_pad(&keyed_inv_cipher,
+                                            init_vector,
+                                            &ciphertext).unwrap();
+            decrypted.truncate(message_len);
+            assert_eq!(decrypted, message);
+        }
+    }
+
+    // Check that batch encryption matches individual encryptions
+    #[test]
+    fn encrypt_messages() {
+        let key = [0x42; 16];
+        let aes_128 = aes::Aes128::new(&key);
+        let messages = vec![b"first".to_vec(),
+                            vec![],
+                            b"a somewhat longer third message".to_vec()];
+        let ivs = [[0x01; 16], [0x02; 16], [0x03; 16]];
+        let keyed_cipher = aes::keyed_cipher_128(&key);
+        let encrypt_one = |mode, iv, message: &[u8]| match mode {
+            BlockMode::Ecb => modes::ecb_encrypt_bytes_128u8(&keyed_cipher,
+                                                             message),
+            BlockMode::Cbc => Cbc.encrypt(&keyed_cipher, iv, message),
+            BlockMode::Ctr => Ctr.encrypt(&keyed_cipher, iv, message),
+            BlockMode::Ofb => Ofb.encrypt(&keyed_cipher, iv, message),
+            BlockMode::Cfb => Cfb.encrypt(&keyed_cipher, iv, message),
+        };
+        for &mode in [BlockMode::Ecb, BlockMode::Cbc, BlockMode::Ctr,
+                      BlockMode::Ofb, BlockMode::Cfb].iter() {
+            let ciphertexts = aes_128.encrypt_messages(&messages, mode, &ivs);
+            assert_eq!(ciphertexts.len(), messages.len());
+            for ((ciphertext, message), iv) in ciphertexts.iter()
+                                                          .zip(messages.iter())
+                                                          .zip(ivs.iter()) {
+                assert_eq!(*ciphertext, encrypt_one(mode, *iv, message));
+            }
+        }
+        assert_eq!(modes::encrypt_messages_128u8(&aes_128,
+                                                 &Cbc,
+                                                 &messages,
+                                                 &ivs),
+                   aes_128.encrypt_messages(&messages, BlockMode::Cbc, &ivs));
+    }
+
+    // Check that CBC encryption of raw bytes can be decrypted
+    #[test]
+    fn cbc_bytes_round_trip() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        for len in 0..40 {
+            let message = (0..len).collect::<Vec<u8>>();
+            let ciphertext = modes::cbc_encrypt_bytes_128u8(&keyed_cipher,
+                                                            init_vector,
+                                                            &message);
+            assert_eq!(ciphertext.len(), (len as usize / 16 + 1) * 16);
+            assert_eq!(modes::inv_cbc_128u8(&keyed_inv_cipher,
+                                            init_vector,
+                                            &ciphertext),
+                       Some(message));
+        }
+    }
+
+    // Check that in-place CBC encryption matches the allocating version
+    #[test]
+    fn cbc_in_place() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        for len in 0..40 {
+            let mut data = (0..len).collect::<Vec<u8>>();
+            let expected = modes::cbc_encrypt_bytes_128u8(&keyed_cipher,
+                                                          init_vector,
+                                                          &data);
+            modes::cbc_encrypt_in_place_128u8(&keyed_cipher,
+                                              init_vector,
+                                              &mut data);
+            assert_eq!(data, expected);
+        }
+    }
+
+    // Check that encrypt-then-MAC round-trips, and rejects tampering
+    #[test]
+    fn cbc_then_hmac() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let mac_key = [0x13; 32];
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = b"Attack at dawn".to_vec();
+        let (mut ciphertext, tag) = modes::cbc_then_hmac_128u8(&keyed_cipher,
+                                                               &mac_key,
+                                                               init_vector,
+                                                               &message);
+        assert_eq!(modes::inv_cbc_then_hmac_128u8(&keyed_inv_cipher,
+                                                  &mac_key,
+                                                  init_vector,
+                                                  &ciphertext,
+                                                  &tag),
+                   Ok(message));
+
+        ciphertext[3] ^= 0x01;
+        assert_eq!(modes::inv_cbc_then_hmac_128u8(&keyed_inv_cipher,
+                                                  &mac_key,
+                                                  init_vector,
+                                                  &ciphertext,
+                                                  &tag),
+                   Err(AuthError::InvalidTag));
+    }
+
+    // Check that sealed messages can be opened, and reject tampering
+    #[test]
+    fn seal_cbc_hmac() {
+        let enc_key = [0x42; 16];
+        let mac_key = [0x13; 32];
+        let message = b"Attack at dawn";
+        let sealed = modes::seal_cbc_hmac(&enc_key, &mac_key, message).unwrap();
+        assert_eq!(sealed.len(), 16 + 16 + 32);
+        assert_eq!(modes::open_cbc_hmac(&enc_key, &mac_key, &sealed),
+                   Ok(message.to_vec()));
+
+        // Each message gets its own IV
+        assert_ne!(sealed,
+                   modes::seal_cbc_hmac(&enc_key, &mac_key, message).unwrap());
+
+        // Any modification of the sealed message is detected
+        for index in [0, 20, 40].iter() {
+            let mut tampered = sealed.clone();
+            tampered[*index] ^= 0x01;
+            assert_eq!(modes::open_cbc_hmac(&enc_key, &mac_key, &tampered),
+                       Err(AuthError::InvalidTag));
+        }
+        assert_eq!(modes::open_cbc_hmac(&enc_key, &mac_key, &sealed[..47]),
+                   Err(AuthError::InvalidTag));
+    }
+
+    // Check that lazy CBC decryption matches the eager version
+    #[test]
+    fn cbc_plaintext_iter() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        for len in 0..40 {
+            let message = (0..len).collect::<Vec<u8>>();
+            let ciphertext = modes::cbc_encrypt_bytes_128u8(&keyed_cipher,
+                                                            init_vector,
+                                                            &message);
+            let chunks = CbcPlaintextIter::new(&keyed_inv_cipher,
+                                               init_vector,
+                                               &ciphertext)
+                             .unwrap()
+                             .collect::<Result<Vec<_>, _>>()
+                             .unwrap();
+            assert_eq!(chunks.len(), ciphertext.len() / BLOCK_LEN_128_U8);
+            assert_eq!(Some(chunks.concat()),
+                       modes::inv_cbc_128u8(&keyed_inv_cipher,
+                                            init_vector,
+                                            &ciphertext));
+        }
+        let mut iter = CbcPlaintextIter::new(&keyed_inv_cipher,
+                                             init_vector,
+                                             &[]).unwrap();
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        assert!(CbcPlaintextIter::new(&keyed_inv_cipher,
+                                      init_vector,
+                                      &[0; 17]).is_none());
+    }
+
+    // Check that IVs are split from the start of ciphertexts
+    #[test]
+    fn split_iv() {
+        let data = (0..16).collect::<Vec<u8>>();
+        let (init_vector, body) = modes::split_iv_128u8(&data).unwrap();
+        assert_eq!(&init_vector[..], &data[..]);
+        assert!(body.is_empty());
+        assert!(modes::split_iv_128u8(&data[..10]).is_none());
+
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = b"Attack at dawn".to_vec();
+        let mut input = init_vector.to_vec();
+        input.extend(modes::cbc_encrypt_bytes_128u8(&keyed_cipher,
+                                                    init_vector,
+                                                    &message));
+        assert_eq!(modes::inv_cbc_128u8_with_iv(&keyed_inv_cipher, &input),
+                   Some(message));
+        assert_eq!(modes::inv_cbc_128u8_with_iv(&keyed_inv_cipher,
+                                                &input[..10]),
+                   None);
+    }
+
+    // Check that unpadded CBC round-trips over block-aligned data
+    #[test]
+    fn cbc_no_pad() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let blocks = [[0x01; BLOCK_LEN_128_U8], [0x02; BLOCK_LEN_128_U8]];
+        let ciphertext = modes::cbc_128u8_no_pad(&keyed_cipher,
+                                                 init_vector,
+                                                 &blocks);
+        assert_eq!(ciphertext.len(), 2 * BLOCK_LEN_128_U8);
+        let plaintext = modes::inv_cbc_128u8_no_pad(&keyed_inv_cipher,
+                                                    init_vector,
+                                                    &ciphertext).unwrap();
+        assert_eq!(plaintext, blocks::into_vec_128u8(blocks.iter().cloned()));
+        assert_eq!(modes::inv_cbc_128u8_no_pad(&keyed_inv_cipher,
+                                               init_vector,
+                                               &ciphertext[1..]),
+                   None);
+    }
+
+    // Check that the last ciphertext block is only extracted when it exists
+    #[test]
+    fn last_block() {
+        let ciphertext = (0..32).collect::<Vec<u8>>();
+        assert_eq!(modes::last_block_128u8(&ciphertext),
+                   Some(blocks::as_block_128u8(&ciphertext[16..])));
+        assert_eq!(modes::last_block_128u8(&[]), None);
+        assert_eq!(modes::last_block_128u8(&ciphertext[..31]), None);
+    }
+
+    // Check that chained CBC calls match a single CBC call
+    #[test]
+    fn cbc_chain() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = (0..50).collect::<Vec<u8>>();
+        let expected = modes::cbc_128u8(&keyed_cipher,
+                                        init_vector,
+                                        PKCS7Padding128u8::new(&message));
+
+        let (head, tail) = message.split_at(2 * BLOCK_LEN_128_U8);
+        let mut prev_block = init_vector;
+        let head_blocks = head.chunks(BLOCK_LEN_128_U8)
+                              .map(|chunk| *blocks::as_block_128u8(chunk));
+        let mut actual = modes::cbc_chain_128u8(&keyed_cipher,
+                                                &mut prev_block,
+                                                head_blocks);
+        assert_eq!(&prev_block[..], &actual[actual.len()-BLOCK_LEN_128_U8..]);
+        actual.extend(modes::cbc_chain_128u8(&keyed_cipher,
+                                             &mut prev_block,
+                                             PKCS7Padding128u8::new(tail)));
+        assert_eq!(actual, expected);
+    }
+
+    // Check the counter sequence of CTR, using an identity "cipher" so that
+    // the keystream is the counter itself
+    #[test]
+    fn ctr_endianness() {
+        let identity = |block: &Block128u8| *block;
+        let mut init_vector = [0; BLOCK_LEN_128_U8];
+        init_vector[0] = 0xfe;
+        init_vector[1] = 0xff;
+        let zeros = [0; 3 * BLOCK_LEN_128_U8];
+
+        let keystream = modes::ctr_128u8_endian(&identity,
+                                                init_vector,
+                                                &zeros,
+                                                CounterEndian::Little);
+        assert_eq!(&keystream[0..3], &[0xfe, 0xff, 0x00]);
+        assert_eq!(&keystream[16..19], &[0xff, 0xff, 0x00]);
+        assert_eq!(&keystream[32..35], &[0x00, 0x00, 0x01]);
+
+        let keystream = modes::ctr_128u8(&identity, init_vector, &zeros);
+        assert_eq!(&keystream[14..16], &[0x00, 0x00]);
+        assert_eq!(&keystream[30..32], &[0x00, 0x01]);
+        assert_eq!(&keystream[46..48], &[0x00, 0x02]);
+    }
+
+    // Check that ECB encryption of raw bytes can be decrypted
+    #[test]
+    fn ecb_bytes_round_trip() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        for len in 0..40 {
+            let message = (0..len).collect::<Vec<u8>>();
+            let ciphertext = modes::ecb_encrypt_bytes_128u8(&keyed_cipher,
+                                                            &message);
+            assert_eq!(ciphertext.len(), (len as usize / 16 + 1) * 16);
+            assert_eq!(modes::ecb_decrypt_bytes_128u8(&keyed_inv_cipher,
+                                                      &ciphertext),
+                       Ok(message));
+        }
+
+        // Identical plaintext blocks yield identical ciphertext blocks
+        let ciphertext = modes::ecb_encrypt_bytes_128u8(&keyed_cipher,
+                                                        &[0x33; 32]);
+        assert_eq!(&ciphertext[..16], &ciphertext[16..32]);
+
+        // Malformed ciphertexts are rejected
+        assert_eq!(modes::ecb_decrypt_bytes_128u8(&keyed_inv_cipher, &[0; 17]),
+                   Err(ModeError::UnalignedInput));
+        assert_eq!(modes::ecb_decrypt_bytes_128u8(&keyed_inv_cipher, &[]),
+                   Err(ModeError::Padding(PadError::Empty)));
+    }
+
+    // Check that numerical counters are interpreted as big-endian IVs
+    #[test]
+    fn ctr_from_u128() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let message = (0..40).collect::<Vec<u8>>();
+        let mut init_vector = [0; BLOCK_LEN_128_U8];
+        init_vector[0] = 0x01;
+        init_vector[14] = 0xff;
+        init_vector[15] = 0xfe;
+        let counter = (1 << 120) | 0xfffe;
+        assert_eq!(modes::ctr_128u8_from_u128(&keyed_cipher, counter, &message),
+                   modes::ctr_128u8(&keyed_cipher, init_vector, &message));
+    }
+
+    // Check that CTR encryption is XOR with the CTR keystream
+    #[test]
+    fn ctr_keystream() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = (0..40).collect::<Vec<u8>>();
+        let mut expected = modes::ctr_keystream_128u8(&keyed_cipher,
+                                                      init_vector,
+                                                      message.len());
+        assert_eq!(expected.len(), message.len());
+        for (byte, message_byte) in expected.iter_mut().zip(message.iter()) {
+            *byte ^= message_byte;
+        }
+        assert_eq!(modes::ctr_128u8(&keyed_cipher, init_vector, &message),
+                   expected);
+    }
+
+    // Check that OFB encryption is XOR with the OFB keystream
+    #[test]
+    fn ofb_keystream() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = (0..40).collect::<Vec<u8>>();
+        let mut expected = modes::ofb_keystream_128u8(&keyed_cipher,
+                                                      init_vector,
+                                                      message.len());
+        assert_eq!(expected.len(), message.len());
+        for (byte, message_byte) in expected.iter_mut().zip(message.iter()) {
+            *byte ^= message_byte;
+        }
+        assert_eq!(modes::ofb_128u8(&keyed_cipher, init_vector, &message),
+                   expected);
+        assert_ne!(modes::ofb_keystream_128u8(&keyed_cipher, init_vector, 40),
+                   modes::ctr_keystream_128u8(&keyed_cipher, init_vector, 40));
+    }
+
+    // Check the split nonce/counter CTR layout, including counter wrap-around
+    #[test]
+    fn ctr_split() {
+        let identity = |block: &Block128u8| *block;
+        let nonce = [0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7];
+        let zeros = [0; 2 * BLOCK_LEN_128_U8 + 5];
+        let keystream = modes::ctr_128u8_split(&identity,
+                                               &nonce,
+                                               0xffff_ffff_ffff_fffe,
+                                               &zeros);
+        assert_eq!(keystream.len(), zeros.len());
+        assert_eq!(&keystream[0..16], &[0xa0, 0xa1, 0xa2, 0xa3,
+                                        0xa4, 0xa5, 0xa6, 0xa7,
+                                        0xff, 0xff, 0xff, 0xff,
+                                        0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(&keystream[16..32], &[0xa0, 0xa1, 0xa2, 0xa3,
+                                         0xa4, 0xa5, 0xa6, 0xa7,
+                                         0xff, 0xff, 0xff, 0xff,
+                                         0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(&keystream[32..], &[0xa0, 0xa1, 0xa2, 0xa3, 0xa4]);
+        let keystream = modes::ctr_128u8_split(&identity, &nonce, 0, &zeros);
+        assert_eq!(&keystream[24..32], &[0, 0, 0, 0, 0, 0, 0, 1]);
+    }
+
+    // Check that stateful CTR encryption of a message split into chunks of
+    // arbitrary size matches one-shot CTR encryption of the whole message
+    #[test]
+    fn ctr_state() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = (0..53).collect::<Vec<u8>>();
+        for chunk_lens in [vec![5, 7, 4], vec![5, 7, 4, 20, 1, 16]].iter() {
+            let mut state = CtrState::new(&keyed_cipher, init_vector);
+            let mut output = Vec::new();
+            let mut start = 0;
+            for &len in chunk_lens.iter() {
+                output.extend(state.process(&message[start..start+len]));
+                start += len;
+            }
+            assert_eq!(output,
+                       modes::ctr_128u8(&keyed_cipher,
+                                        init_vector,
+                                        &message[..start]));
+        }
+    }
+
+    // Check that CTR sessions reject IV reuse
+    #[test]
+    fn ctr_session() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let iv_1 = [0x24; BLOCK_LEN_128_U8];
+        let iv_2 = [0x25; BLOCK_LEN_128_U8];
+        let message = b"Attack at dawn";
+        let mut session = CtrSession::new(&keyed_cipher);
+        assert_eq!(session.encrypt(iv_1, message),
+                   Ok(modes::ctr_128u8(&keyed_cipher, iv_1, message)));
+        assert!(session.encrypt(iv_2, message).is_ok());
+        assert_eq!(session.encrypt(iv_1, message), Err(IvReuseError(iv_1)));
+    }
+
+    // Check OFB and CFB against the first blocks of the NIST SP 800-38A
+    // AES-128 example vectors
+    #[test]
+    fn ofb_cfb_examples() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x2b, 0x7e, 0x15, 0x16,
+                                                   0x28, 0xae, 0xd2, 0xa6,
+                                                   0xab, 0xf7, 0x15, 0x88,
+                                                   0x09, 0xcf, 0x4f, 0x3c]);
+        let init_vector = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+                           0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+        let plaintext = hexfile::parse_hex("6bc1bee22e409f96e93d7e117393172a\
+                                            ae2d8a571e03ac9c9eb76fac45af8e51")
+                                          .unwrap();
+        assert_eq!(hexfile::to_hex(&modes::ofb_128u8(&keyed_cipher,
+                                                     init_vector,
+                                                     &plaintext)),
+                   "3b3fd92eb72dad20333449f8e83cfb4a\
+                    7789508d16918f03f53c52dac54ed825");
+        assert_eq!(hexfile::to_hex(&modes::cfb_128u8(&keyed_cipher,
+                                                     init_vector,
+                                                     &plaintext)),
+                   "3b3fd92eb72dad20333449f8e83cfb4a\
+                    c8a64537a0b3a93fcde3cdad9f1ce58b");
+    }
+
+    // Check ciphertext length validation for each mode
+    #[test]
+    fn validate_ciphertext_len() {
+        assert!(!modes::validate_ciphertext_len(15, BlockMode::Cbc));
+        assert!(!modes::validate_ciphertext_len(0, BlockMode::Cbc));
+        assert!(modes::validate_ciphertext_len(32, BlockMode::Cbc));
+        assert!(!modes::validate_ciphertext_len(17, BlockMode::Ecb));
+        assert!(modes::validate_ciphertext_len(15, BlockMode::Ctr));
+        assert!(modes::validate_ciphertext_len(0, BlockMode::Ofb));
+        assert!(modes::validate_ciphertext_len(7, BlockMode::Cfb));
+    }
+
+    // Check that all modes round-trip through the StreamMode interface, both
+    // with separate keyed cipher closures and with a keyed cipher object
+    fn check_round_trip<M: StreamMode>(mode: M) {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let cipher = modes::keyed_aes_128(&[0x42; 16]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        for len in 0..40 {
+            let message = (0..len).collect::<Vec<u8>>();
+            let ciphertext = mode.encrypt(&keyed_cipher, init_vector, &message);
+            assert_eq!(mode.encrypt(&cipher, init_vector, &message),
+                       ciphertext);
+            assert_eq!(mode.decrypt(&cipher, &cipher, init_vector, &ciphertext),
+                       Some(message.clone()));
+            assert_eq!(mode.decrypt(&keyed_cipher,
+                                    &keyed_inv_cipher,
+                                    init_vector,
+                                    &ciphertext),
+                       Some(message));
+        }
+    }
+    //
+    #[test]
+    fn stream_mode_round_trip() {
+        check_round_trip(Cbc);
+        check_round_trip(Ctr);
+        check_round_trip(Ofb);
+        check_round_trip(Cfb);
+    }
+
+    // Measure the encryption throughput of a mode, in megabytes per second, by
+    // repeatedly encrypting a buffer of the specified size with AES-128 until
+    // enough time has elapsed for the measurement to be meaningful. This is
+    // only a rough estimate, which is best measured on a release build.
+    fn throughput_mb_per_s<M: StreamMode>(mode: &M, size: usize) -> f64 {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 128/8]);
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let data = vec![0x5a; size];
+        let mut total_bytes = 0;
+        let start = Instant::now();
+        loop {
+            let ciphertext = mode.encrypt(&keyed_cipher, init_vector, &data);
+            assert!(ciphertext.len() >= size);
+            total_bytes += size;
+            let elapsed = start.elapsed();
+            if elapsed >= Duration::from_millis(100) {
+                return total_bytes as f64 / 1e6 / elapsed.as_secs_f64();
+            }
+        }
+    }
+
+    // Print the throughput of each mode. This test is ignored by default since
+    // it takes a while and is only meaningful on a release build.
+    #[test]
+    #[ignore]
+    fn throughput() {
+        let size = 16 * 1024 * 1024;
+        println!("CBC: {:.1} MB/s", throughput_mb_per_s(&Cbc, size));
+        println!("CTR: {:.1} MB/s", throughput_mb_per_s(&Ctr, size));
+        println!("OFB: {:.1} MB/s", throughput_mb_per_s(&Ofb, size));
+        println!("CFB: {:.1} MB/s", throughput_mb_per_s(&Cfb, size));
+    }
+}
@@ -0,0 +1,66 @@
+//! Key whitening, as used by DES-X, strengthens a block cipher against brute
+//! force by XORing the block with extra key material before and after the
+//! encryption. This module applies this construction to 128-bit ciphers.
+
+use block_ciphers::{DecryptBlock128, EncryptBlock128};
+use blocks::{self, Block128u8};
+
+
+// Encrypt a block with pre- and post-whitening: post ^ E(block ^ pre)
+pub fn encrypt<KC>(pre: &Block128u8,
+                   post: &Block128u8,
+                   keyed_cipher: &KC,
+                   block: &Block128u8) -> Block128u8
+    where KC: EncryptBlock128
+{
+    let whitened_input = blocks::xor_block_128u8(block, pre);
+    blocks::xor_block_128u8(&keyed_cipher.encrypt_block(&whitened_input), post)
+}
+
+// Decrypt a whitened block, undoing the steps of encryption in reverse order:
+// pre ^ D(block ^ post)
+pub fn decrypt<KIC>(pre: &Block128u8,
+                    post: &Block128u8,
+                    keyed_inv_cipher: &KIC,
+                    block: &Block128u8) -> Block128u8
+    where KIC: DecryptBlock128
+{
+    let whitened_input = blocks::xor_block_128u8(block, post);
+    let decrypted = keyed_inv_cipher.decrypt_block(&whitened_input);
+    blocks::xor_block_128u8(&decrypted, pre)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use block_ciphers::aes;
+    use block_ciphers::modes;
+    use block_ciphers::whitened;
+
+    #[test]
+    fn round_trip() {
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let keyed_inv_cipher = aes::keyed_inv_cipher_128(&[0x42; 16]);
+        let (pre, post) = ([0x5a; 16], [0xc3; 16]);
+        let plaintext = [0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d,
+                         0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34];
+        let ciphertext = whitened::encrypt(&pre, &post, &keyed_cipher,
+                                           &plaintext);
+        assert_ne!(ciphertext, keyed_cipher(&plaintext));
+        assert_eq!(whitened::decrypt(&pre, &post, &keyed_inv_cipher,
+                                     &ciphertext),
+                   plaintext);
+
+        // Keyed cipher objects may be used in both directions as well
+        let cipher = modes::keyed_aes_128(&[0x42; 16]);
+        assert_eq!(whitened::encrypt(&pre, &post, &cipher, &plaintext),
+                   ciphertext);
+        assert_eq!(whitened::decrypt(&pre, &post, &cipher, &ciphertext),
+                   plaintext);
+
+        // Without whitening keys, the construction reduces to the cipher
+        let zero = [0; 16];
+        assert_eq!(whitened::encrypt(&zero, &zero, &keyed_cipher, &plaintext),
+                   keyed_cipher(&plaintext));
+    }
+}
@@ -0,0 +1,225 @@
+//! This module provides streaming versions of the feedback modes of operation,
+//! which encrypt data as it is written into an underlying io::Write sink.
+//!
+//! Unlike one-shot mode functions, these writers must remember the feedback
+//! register across write() calls, since the input may be split arbitrarily.
+
+use block_ciphers::EncryptBlock128;
+use blocks::{Block128u8, BLOCK_LEN_128_U8};
+use std::io::{self, Write};
+
+
+// Streaming OFB encryption/decryption (OFB is its own inverse). The keystream
+// block is only partially used when a write ends in the middle of a block.
+pub struct OfbWriter<KC, W> {
+    keyed_cipher: KC,
+    inner: W,
+    keystream_block: Block128u8,
+    used_bytes: usize,
+}
+//
+impl<KC, W> OfbWriter<KC, W>
+    where KC: EncryptBlock128,
+          W: Write
+{
+    // Start writing a message, which will be forwarded to an inner writer
+    pub fn new(keyed_cipher: KC, init_vector: Block128u8, inner: W) -> Self {
+        Self {
+            keyed_cipher,
+            inner,
+            keystream_block: init_vector,
+            used_bytes: BLOCK_LEN_128_U8,
+        }
+    }
+
+    // Retrieve the inner writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+//
+impl<KC, W> Write for OfbWriter<KC, W>
+    where KC: EncryptBlock128,
+          W: Write
+{
+    // The keystream state is only updated once the output has been written,
+    // so that a failed write can be retried with the same keystream
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = buf.to_vec();
+        let mut keystream_block = self.keystream_block;
+        let mut used_bytes = self.used_bytes;
+        for byte in output.iter_mut() {
+            if used_bytes == BLOCK_LEN_128_U8 {
+                keystream_block =
+                    self.keyed_cipher.encrypt_block(&keystream_block);
+                used_bytes = 0;
+            }
+            *byte ^= keystream_block[used_bytes];
+            used_bytes += 1;
+        }
+        self.inner.write_all(&output)?;
+        self.keystream_block = keystream_block;
+        self.used_bytes = used_bytes;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+// Streaming (full-block) CFB encryption. The feedback register is filled with
+// ciphertext bytes as they are produced, and only encrypted into the next
+// keystream block once it is complete.
+pub struct CfbWriter<KC, W> {
+    keyed_cipher: KC,
+    inner: W,
+    register: Block128u8,
+    keystream_block: Block128u8,
+    used_bytes: usize,
+}
+//
+impl<KC, W> CfbWriter<KC, W>
+    where KC: EncryptBlock128,
+          W: Write
+{
+    // Start writing a message, which will be forwarded to an inner writer
+    pub fn new(keyed_cipher: KC, init_vector: Block128u8, inner: W) -> Self {
+        Self {
+            keyed_cipher,
+            inner,
+            register: init_vector,
+            keystream_block: [0; BLOCK_LEN_128_U8],
+            used_bytes: BLOCK_LEN_128_U8,
+        }
+    }
+
+    // Retrieve the inner writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+//
+impl<KC, W> Write for CfbWriter<KC, W>
+    where KC: EncryptBlock128,
+          W: Write
+{
+    // As with OFB, the state is only updated once the output has been written
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = buf.to_vec();
+        let mut register = self.register;
+        let mut keystream_block = self.keystream_block;
+        let mut used_bytes = self.used_bytes;
+        for byte in output.iter_mut() {
+            if used_bytes == BLOCK_LEN_128_U8 {
+                keystream_block = self.keyed_cipher.encrypt_block(&register);
+                used_bytes = 0;
+            }
+            *byte ^= keystream_block[used_bytes];
+            register[used_bytes] = *byte;
+            used_bytes += 1;
+        }
+        self.inner.write_all(&output)?;
+        self.register = register;
+        self.keystream_block = keystream_block;
+        self.used_bytes = used_bytes;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use block_ciphers::aes;
+    use block_ciphers::modes;
+    use block_ciphers::writers::{CfbWriter, OfbWriter};
+    use blocks::BLOCK_LEN_128_U8;
+    use std::io::{self, Write};
+
+    // Writer which fails on its first write, then accumulates bytes in memory
+    struct FlakyWriter {
+        failed: bool,
+        data: Vec<u8>,
+    }
+    //
+    impl Write for FlakyWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if !self.failed {
+                self.failed = true;
+                return Err(io::Error::new(io::ErrorKind::Other,
+                                          "Simulated write failure"));
+            }
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Write a message into a writer using chunks of various sizes
+    fn write_in_odd_chunks<W: Write>(writer: &mut W, message: &[u8]) {
+        let mut remaining = message;
+        for chunk_len in [1, 3, 7, 17, 0, 5].iter().cycle() {
+            if remaining.is_empty() { break; }
+            let chunk_len = (*chunk_len).min(remaining.len());
+            writer.write_all(&remaining[..chunk_len]).unwrap();
+            remaining = &remaining[chunk_len..];
+        }
+    }
+
+    #[test]
+    fn ofb_writer() {
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = (0..100).collect::<Vec<u8>>();
+        let mut writer = OfbWriter::new(aes::keyed_cipher_128(&[0x42; 16]),
+                                        init_vector,
+                                        Vec::new());
+        write_in_odd_chunks(&mut writer, &message);
+        assert_eq!(writer.into_inner(),
+                   modes::ofb_128u8(&aes::keyed_cipher_128(&[0x42; 16]),
+                                    init_vector,
+                                    &message));
+    }
+
+    #[test]
+    fn cfb_writer() {
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = (0..100).collect::<Vec<u8>>();
+        let mut writer = CfbWriter::new(aes::keyed_cipher_128(&[0x42; 16]),
+                                        init_vector,
+                                        Vec::new());
+        write_in_odd_chunks(&mut writer, &message);
+        assert_eq!(writer.into_inner(),
+                   modes::cfb_128u8(&aes::keyed_cipher_128(&[0x42; 16]),
+                                    init_vector,
+                                    &message));
+    }
+
+    // Check that a failed write can be retried without desynchronizing the
+    // keystream
+    #[test]
+    fn retry_after_failure() {
+        let init_vector = [0x24; BLOCK_LEN_128_U8];
+        let message = (0..40).collect::<Vec<u8>>();
+        let keyed_cipher = aes::keyed_cipher_128(&[0x42; 16]);
+        let inner = || FlakyWriter { failed: false, data: Vec::new() };
+
+        let mut writer = OfbWriter::new(&keyed_cipher, init_vector, inner());
+        writer.write_all(&message[..5]).unwrap_err();
+        writer.write_all(&message).unwrap();
+        assert_eq!(writer.into_inner().data,
+                   modes::ofb_128u8(&keyed_cipher, init_vector, &message));
+
+        let mut writer = CfbWriter::new(&keyed_cipher, init_vector, inner());
+        writer.write_all(&message[..5]).unwrap_err();
+        writer.write_all(&message).unwrap();
+        assert_eq!(writer.into_inner().data,
+                   modes::cfb_128u8(&keyed_cipher, init_vector, &message));
+    }
+}
@@ -27,14 +27,232 @@ pub fn as_mut_block_128u8(slice: &mut [u8]) -> &mut Block128u8 {
     array_mut_ref!(slice, 0, BLOCK_LEN_128_U8)
 }
 
-// Convert a stream of blocks back into a vector of bytes
-pub fn into_vec_128u8<I>(block_iter: I) -> Vec<u8>
-    where I: Iterator<Item=Block128u8>
+/// In hot loops where the slice length is an invariant, the length check of
+/// as_block_128u8 may be skipped. Note that array_ref! would still check the
+/// slice bounds, so the conversion is done with a pointer cast instead.
+///
+/// # Safety
+///
+/// The slice must be at least BLOCK_LEN_128_U8 bytes long. Only its first
+/// BLOCK_LEN_128_U8 bytes are covered by the output block.
+pub unsafe fn as_block_128u8_unchecked(slice: &[u8]) -> &Block128u8 {
+    debug_assert!(slice.len() >= BLOCK_LEN_128_U8);
+    &*(slice.as_ptr() as *const Block128u8)
+}
+
+/// Possible errors when converting slices into blocks
+#[derive(Debug, Eq, PartialEq)]
+pub enum BlockError {
+    /// The slice does not have the length of a block
+    WrongLength { expected: usize, got: usize },
+}
+
+// The conversions above panic on length mismatch, which is fine for internal
+// use. When parsing untrusted input, use this checked version instead.
+pub fn try_as_block_128u8(slice: &[u8]) -> Result<&Block128u8, BlockError> {
+    if slice.len() != BLOCK_LEN_128_U8 {
+        return Err(BlockError::WrongLength { expected: BLOCK_LEN_128_U8,
+                                             got: slice.len() });
+    }
+    Ok(array_ref!(slice, 0, BLOCK_LEN_128_U8))
+}
+
+// XOR two blocks with one another, producing a new block
+pub fn xor_block_128u8(a: &Block128u8, b: &Block128u8) -> Block128u8 {
+    let mut result = *a;
+    for (acc, byte) in result.iter_mut().zip(b.iter()) {
+        *acc ^= *byte;
+    }
+    result
+}
+
+// XOR a block in place with a key, repeating the key if it is shorter than a
+// block, as in repeating-key XOR ciphers (an empty key leaves the block as is)
+pub fn xor_cycle_128u8(block: &mut Block128u8, key: &[u8]) {
+    for (acc, byte) in block.iter_mut().zip(key.iter().cycle()) {
+        *acc ^= *byte;
+    }
+}
+
+// Select block a if cond is true and block b otherwise, without branching on
+// cond: the condition is turned into an all-ones or all-zeros byte mask, which
+// is then used to combine the bytes of both blocks.
+pub fn ct_select_128u8(cond: bool,
+                       a: &Block128u8,
+                       b: &Block128u8) -> Block128u8 {
+    let mask = (cond as u8).wrapping_neg();
+    let mut result = [0; BLOCK_LEN_128_U8];
+    for ((out, byte_a), byte_b) in result.iter_mut().zip(a.iter())
+                                                    .zip(b.iter()) {
+        *out = (byte_a & mask) | (byte_b & !mask);
+    }
+    result
+}
+
+// Reverse the order of the bytes of some data in place, e.g. to interoperate
+// with tools which use the opposite endianness
+pub fn reverse_bytes(data: &mut [u8]) {
+    data.reverse();
+}
+
+// Produce a copy of a block with its bytes in reverse order
+pub fn reverse_block_128u8(block: &Block128u8) -> Block128u8 {
+    let mut result = *block;
+    reverse_bytes(&mut result);
+    result
+}
+
+// Multiply two blocks interpreted as elements of GF(2^128), using the
+// bit-reflected convention of GHASH: the first bit of a block is the
+// coefficient of x^0, and the reduction polynomial is x^128 + x^7 + x^2 + x + 1
+pub fn gf128_mul(x: &Block128u8, y: &Block128u8) -> Block128u8 {
+    let mut z = [0u8; BLOCK_LEN_128_U8];
+    let mut v = *y;
+    for bit in 0..BLOCK_LEN_128_U8 * 8 {
+        // Accumulate V into Z for each bit of X which is set
+        if (x[bit / 8] >> (7 - bit % 8)) & 1 != 0 {
+            z = xor_block_128u8(&z, &v);
+        }
+
+        // Multiply V by x, which in the reflected convention is a right shift,
+        // and reduce the result modulo the GHASH polynomial
+        let overflow = v[BLOCK_LEN_128_U8 - 1] & 1;
+        for index in (1..BLOCK_LEN_128_U8).rev() {
+            v[index] = (v[index] >> 1) | (v[index - 1] << 7);
+        }
+        v[0] >>= 1;
+        v[0] ^= overflow * 0xe1;
+    }
+    z
+}
+
+// Convert a stream of blocks of bytes back into a vector of bytes
+pub fn into_vec<const N: usize, I>(block_iter: I) -> Vec<u8>
+    where I: Iterator<Item=[u8; N]>
 {
-    let result_size = block_iter.size_hint().0 * BLOCK_LEN_128_U8;
+    let result_size = block_iter.size_hint().0 * N;
     let mut result = Vec::with_capacity(result_size);
     for block in block_iter {
         result.extend_from_slice(&block[..]);
     }
     result
 }
+//
+pub fn into_vec_128u8<I>(block_iter: I) -> Vec<u8>
+    where I: Iterator<Item=Block128u8>
+{
+    into_vec::<BLOCK_LEN_128_U8, _>(block_iter)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use blocks::{self, BlockError};
+    use std::ptr;
+
+    #[test]
+    fn reverse() {
+        let block = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+                     0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+        let reversed = blocks::reverse_block_128u8(&block);
+        assert_eq!(reversed, [0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09, 0x08,
+                              0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0x00]);
+        assert_eq!(blocks::reverse_block_128u8(&reversed), block);
+
+        let mut data = vec![1, 2, 3, 4, 5];
+        blocks::reverse_bytes(&mut data);
+        assert_eq!(data, vec![5, 4, 3, 2, 1]);
+        blocks::reverse_bytes(&mut data);
+        assert_eq!(data, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn as_block_128u8_unchecked() {
+        let bytes = (0..16).collect::<Vec<u8>>();
+        let checked = blocks::as_block_128u8(&bytes);
+        let unchecked = unsafe { blocks::as_block_128u8_unchecked(&bytes) };
+        assert!(ptr::eq(checked, unchecked));
+    }
+
+    #[test]
+    fn try_as_block_128u8() {
+        let bytes = (0..20).collect::<Vec<u8>>();
+        assert_eq!(blocks::try_as_block_128u8(&bytes[..16]),
+                   Ok(blocks::as_block_128u8(&bytes[..16])));
+        assert_eq!(blocks::try_as_block_128u8(&bytes[..15]),
+                   Err(BlockError::WrongLength { expected: 16, got: 15 }));
+        assert_eq!(blocks::try_as_block_128u8(&bytes[..]),
+                   Err(BlockError::WrongLength { expected: 16, got: 20 }));
+    }
+
+    #[test]
+    fn xor_block_128u8() {
+        let a = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+                 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+        let b = [0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+                 0x0f, 0xf0, 0x0f, 0xf0, 0x55, 0xaa, 0x55, 0xaa];
+        assert_eq!(blocks::xor_block_128u8(&a, &b),
+                   [0xff, 0xfe, 0xfd, 0xfc, 0x04, 0x05, 0x06, 0x07,
+                    0x07, 0xf9, 0x05, 0xfb, 0x59, 0xa7, 0x5b, 0xa5]);
+    }
+
+    #[test]
+    fn xor_cycle_128u8() {
+        let a = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+                 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+        let b = [0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+                 0x0f, 0xf0, 0x0f, 0xf0, 0x55, 0xaa, 0x55, 0xaa];
+        let mut block = a;
+        blocks::xor_cycle_128u8(&mut block, &b);
+        assert_eq!(block, blocks::xor_block_128u8(&a, &b));
+
+        let mut block = a;
+        blocks::xor_cycle_128u8(&mut block, &[0xff, 0x00, 0x0f, 0xf0]);
+        assert_eq!(block, [0xff, 0x01, 0x0d, 0xf3, 0xfb, 0x05, 0x09, 0xf7,
+                           0xf7, 0x09, 0x05, 0xfb, 0xf3, 0x0d, 0x01, 0xff]);
+    }
+
+    #[test]
+    fn ct_select_128u8() {
+        let a = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+                 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f];
+        let b = [0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00,
+                 0x0f, 0xf0, 0x0f, 0xf0, 0x55, 0xaa, 0x55, 0xaa];
+        for &cond in [false, true].iter() {
+            let expected = if cond { a } else { b };
+            assert_eq!(blocks::ct_select_128u8(cond, &a, &b), expected);
+        }
+    }
+
+    // Check the GHASH steps of test case 2 from the GCM specification
+    #[test]
+    fn gf128_mul() {
+        let h = [0x66, 0xe9, 0x4b, 0xd4, 0xef, 0x8a, 0x2c, 0x3b,
+                 0x88, 0x4c, 0xfa, 0x59, 0xca, 0x34, 0x2b, 0x2e];
+        let c = [0x03, 0x88, 0xda, 0xce, 0x60, 0xb6, 0xa3, 0x92,
+                 0xf3, 0x28, 0xc2, 0xb9, 0x71, 0xb2, 0xfe, 0x78];
+        let x_1 = blocks::gf128_mul(&c, &h);
+        assert_eq!(x_1, [0x5e, 0x2e, 0xc7, 0x46, 0x91, 0x70, 0x62, 0x88,
+                         0x2c, 0x85, 0xb0, 0x68, 0x53, 0x53, 0xde, 0xb7]);
+        let mut len_block = [0; 16];
+        len_block[15] = 0x80;
+        let x_2 = blocks::gf128_mul(&blocks::xor_block_128u8(&x_1, &len_block),
+                                    &h);
+        assert_eq!(x_2, [0xf3, 0x8c, 0xbb, 0x1a, 0xd6, 0x92, 0x23, 0xdc,
+                         0xc3, 0x45, 0x7a, 0xe5, 0xb6, 0xb0, 0xf8, 0x85]);
+
+        // Multiplication is commutative, and the unit element is 0x80 0x00...
+        let mut one = [0; 16];
+        one[0] = 0x80;
+        assert_eq!(blocks::gf128_mul(&h, &c), x_1);
+        assert_eq!(blocks::gf128_mul(&h, &one), h);
+    }
+
+    #[test]
+    fn into_vec() {
+        let block_iter = vec![[1, 2, 3, 4, 5, 6, 7, 8],
+                              [9, 10, 11, 12, 13, 14, 15, 16]].into_iter();
+        assert_eq!(blocks::into_vec::<8, _>(block_iter),
+                   (1..17).collect::<Vec<u8>>());
+    }
+}
@@ -0,0 +1,347 @@
+//! Tools for studying the behaviour of cryptographic primitives, as is done in
+//! the cryptanalysis parts of Coursera's crypto MOOC
+
+use block_ciphers::EncryptBlock128;
+use block_ciphers::aes::{self, Input, Key128};
+use blocks::{Block128u8, BLOCK_LEN_128_U8};
+use display::printable_ratio;
+use {hamming_distance, max_length};
+use std::ops::Range;
+
+
+// Measure the avalanche effect of AES-128: flip each of the 128 plaintext bits
+// in turn, encrypt the result, and report how many bits of the ciphertext
+// changed with respect to the encryption of the original plaintext. A cipher
+// with good diffusion should flip about half of the output bits every time.
+pub fn avalanche(key: &Key128, pt: &Input) -> Vec<u32> {
+    avalanche_of(&aes::keyed_cipher_128(key), pt)
+}
+
+// The same measurement can be carried out on any keyed block cipher, e.g. on
+// weakened AES variants, in order to compare their diffusion properties
+pub fn avalanche_of<KC>(keyed_cipher: &KC, pt: &Block128u8) -> Vec<u32>
+    where KC: EncryptBlock128
+{
+    // Compute the baseline ciphertext
+    let baseline = keyed_cipher.encrypt_block(pt);
+
+    // Flip every plaintext bit and measure the resulting ciphertext change
+    let mut distances = Vec::with_capacity(BLOCK_LEN_128_U8 * 8);
+    for bit in 0..BLOCK_LEN_128_U8 * 8 {
+        let mut flipped_pt = *pt;
+        flipped_pt[bit / 8] ^= 0x80 >> (bit % 8);
+        let ciphertext = keyed_cipher.encrypt_block(&flipped_pt);
+        distances.push(hamming_distance(&baseline, &ciphertext));
+    }
+    distances
+}
+
+
+// Count how many times each byte value occurs in a message
+pub fn byte_histogram(data: &[u8]) -> [usize; 256] {
+    let mut histogram = [0; 256];
+    for &byte in data {
+        histogram[byte as usize] += 1;
+    }
+    histogram
+}
+
+// Find the byte value which occurs most often in a message. Ties are resolved
+// in favor of the smallest byte value, and an empty message yields zero.
+pub fn most_common_byte(data: &[u8]) -> u8 {
+    let histogram = byte_histogram(data);
+    let mut best_byte = 0;
+    for (byte, &count) in histogram.iter().enumerate() {
+        if count > histogram[best_byte] {
+            best_byte = byte;
+        }
+    }
+    best_byte as u8
+}
+
+
+// Decrypt a ciphertext with every possible single-byte XOR key, returning each
+// key byte along with the associated decryption. Scoring the candidates is
+// left to the caller, e.g. by displaying them with print_columns.
+pub fn all_single_byte_xors(ciphertext: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    (0..=255u8).map(|key| {
+                   let decryption = ciphertext.iter()
+                                              .map(|byte| byte ^ key)
+                                              .collect();
+                   (key, decryption)
+               })
+               .collect()
+}
+
+
+// When several messages were encrypted with the same keystream (many-time pad),
+// each column of the keystream can be attacked using the messages which reach
+// it. This tells, for each column up to the length of the longest message, how
+// many messages are available, i.e. how much the key recovery can be trusted.
+pub fn column_sample_counts(messages: &[Vec<u8>]) -> Vec<usize> {
+    let columns = max_length(messages).unwrap_or(0);
+    (0..columns).map(|column| {
+                    messages.iter()
+                            .filter(|message| message.len() > column)
+                            .count()
+                })
+                .collect()
+}
+
+
+// Count the positions at which two ciphertexts have identical blocks. With ECB,
+// identical plaintext blocks always yield identical ciphertext blocks, whereas
+// CBC and CTR hide this structure when the IVs differ. Ciphertexts which are
+// not block-aligned are considered to have no common block.
+pub fn common_blocks(a: &[u8], b: &[u8]) -> usize {
+    if a.len() % BLOCK_LEN_128_U8 != 0
+       || b.len() % BLOCK_LEN_128_U8 != 0 {
+        return 0;
+    }
+    a.chunks(BLOCK_LEN_128_U8)
+     .zip(b.chunks(BLOCK_LEN_128_U8))
+     .filter(|(block_a, block_b)| block_a == block_b)
+     .count()
+}
+
+
+// Estimate the key size of a repeating-key XOR (Vigenère-like) cipher. For
+// each candidate key size, we compute the Hamming distance between successive
+// ciphertext blocks of that size, normalized by the key size. With the right
+// key size, these blocks are XORs of plaintext with the same key, so their
+// distance is that of the plaintext bytes, which is lower than that of random
+// bytes for natural language. The key size with the smallest distance wins.
+//
+// However, multiples of the true key size also align blocks with the key, so
+// they get about the same distance as the key size itself, and statistical
+// noise regularly makes one of them score slightly lower. Picking it would
+// yield a key made of several copies of the true key. So a larger key size
+// only replaces the best one so far if it improves upon its distance by more
+// than KEY_SIZE_IMPROVEMENT_THRESHOLD, which favors smaller sizes on near-ties.
+//
+// Key sizes for which the ciphertext does not contain two full blocks cannot
+// be evaluated. If no candidate key size can be, the smallest nonzero one is
+// returned, so callers should check that the ciphertext holds two key-sized
+// blocks before trusting the result.
+pub fn guess_key_size(ciphertext: &[u8], range: Range<usize>) -> usize {
+    let mut best_key_size = range.start.max(1);
+    let mut best_distance = f64::INFINITY;
+    for key_size in range {
+        let distance = match key_size_distance(ciphertext, key_size) {
+            Some(distance) => distance,
+            None => continue,
+        };
+        if distance < best_distance * KEY_SIZE_IMPROVEMENT_THRESHOLD {
+            best_key_size = key_size;
+            best_distance = distance;
+        }
+    }
+    best_key_size
+}
+//
+// A larger key size must reduce the best distance so far by more than 5%
+const KEY_SIZE_IMPROVEMENT_THRESHOLD: f64 = 0.95;
+//
+// Average normalized Hamming distance between successive ciphertext blocks of
+// some size, or None if there are less than two such blocks
+fn key_size_distance(ciphertext: &[u8], key_size: usize) -> Option<f64> {
+    if key_size == 0 || ciphertext.len() < 2 * key_size { return None; }
+    let blocks = ciphertext.chunks(key_size)
+                           .filter(|block| block.len() == key_size)
+                           .collect::<Vec<_>>();
+    let total_distance = blocks.windows(2)
+                               .map(|pair| hamming_distance(pair[0], pair[1]))
+                               .sum::<u32>();
+    let num_pairs = (blocks.len() - 1) as f64;
+    Some(total_distance as f64 / num_pairs / key_size as f64)
+}
+
+// Break a repeating-key XOR cipher whose plaintext is ASCII text. The key size
+// is first estimated, then each key byte is recovered independently by trying
+// all single-byte XORs on the ciphertext bytes which it encrypts, and keeping
+// the most text-like decryption. Returns the key and the plaintext, or None if
+// the key size could not be estimated.
+pub fn break_repeating_key_xor(ciphertext: &[u8],
+                               key_sizes: Range<usize>)
+                               -> Option<(Vec<u8>, Vec<u8>)> {
+    let key_size = guess_key_size(ciphertext, key_sizes);
+    key_size_distance(ciphertext, key_size)?;
+    let key = (0..key_size).map(|offset| {
+                               let column = ciphertext.iter()
+                                                      .skip(offset)
+                                                      .step_by(key_size)
+                                                      .cloned()
+                                                      .collect::<Vec<_>>();
+                               best_single_byte_key(&column)
+                           })
+                           .collect::<Vec<_>>();
+    let plaintext = ciphertext.iter()
+                              .zip(key.iter().cycle())
+                              .map(|(byte, key_byte)| byte ^ key_byte)
+                              .collect();
+    Some((key, plaintext))
+}
+
+// Find the single-byte XOR key which produces the most text-like decryption.
+// Decryptions are ranked by their fraction of printable characters, and then,
+// since many keys produce printable output, by their fraction of letters and
+// spaces. Ties are resolved in favor of the smallest key byte.
+fn best_single_byte_key(ciphertext: &[u8]) -> u8 {
+    let score = |decryption: &[u8]| {
+        let letters = decryption.iter()
+                                .filter(|&&byte| byte.is_ascii_alphabetic()
+                                                 || byte == b' ')
+                                .count();
+        let letter_ratio = letters as f64 / decryption.len().max(1) as f64;
+        printable_ratio(decryption) + letter_ratio
+    };
+    let mut best_key = 0;
+    let mut best_score = f64::NEG_INFINITY;
+    for (key, decryption) in all_single_byte_xors(ciphertext) {
+        let score = score(&decryption);
+        if score > best_score {
+            best_key = key;
+            best_score = score;
+        }
+    }
+    best_key
+}
+
+
+#[cfg(test)]
+mod tests {
+    use block_ciphers::aes::{self, Input, Key128};
+    use block_ciphers::modes;
+    use cryptanalysis;
+    use inplace_xor_bytes;
+
+    const KEY: Key128 = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+                         0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+    const PLAINTEXT: Input = [0x32, 0x43, 0xf6, 0xa8, 0x88, 0x5a, 0x30, 0x8d,
+                              0x31, 0x31, 0x98, 0xa2, 0xe0, 0x37, 0x07, 0x34];
+
+    // On average, flipping one input bit should flip half of the output bits
+    #[test]
+    fn avalanche() {
+        let distances = cryptanalysis::avalanche(&KEY, &PLAINTEXT);
+        assert_eq!(distances.len(), 128);
+        let mean = distances.iter().sum::<u32>() as f64 / 128.0;
+        assert!((mean - 64.0).abs() < 4.0);
+    }
+
+    // Without MixColumns, a plaintext bit only affects one ciphertext byte
+    #[test]
+    fn avalanche_no_mixcolumns() {
+        let round_keys = aes::key_expansion_128(&KEY);
+        let keyed_cipher = |input: &Input| {
+            aes::cipher_no_mixcolumns(input, &round_keys)
+        };
+        let distances = cryptanalysis::avalanche_of(&keyed_cipher, &PLAINTEXT);
+        assert_eq!(distances.len(), 128);
+        assert!(distances.iter().all(|&distance| distance <= 8));
+    }
+
+    #[test]
+    fn byte_histogram() {
+        let data = b"hello world";
+        let histogram = cryptanalysis::byte_histogram(data);
+        assert_eq!(histogram[b'l' as usize], 3);
+        assert_eq!(histogram[b'o' as usize], 2);
+        assert_eq!(histogram[b'z' as usize], 0);
+        assert_eq!(histogram.iter().sum::<usize>(), data.len());
+        assert_eq!(cryptanalysis::most_common_byte(data), b'l');
+        assert_eq!(cryptanalysis::most_common_byte(b"ba"), b'a');
+    }
+
+    #[test]
+    fn all_single_byte_xors() {
+        let ciphertext = b"\x1b\x37\x37\x33";
+        let candidates = cryptanalysis::all_single_byte_xors(ciphertext);
+        assert_eq!(candidates.len(), 256);
+        assert_eq!(candidates[0], (0, ciphertext.to_vec()));
+        assert_eq!(candidates[0x58], (0x58, b"Cook".to_vec()));
+    }
+
+    #[test]
+    fn column_sample_counts() {
+        let messages = vec![vec![1, 2, 3], vec![4], vec![], vec![5, 6, 7, 8]];
+        assert_eq!(cryptanalysis::column_sample_counts(&messages),
+                   vec![3, 2, 2, 1]);
+        assert_eq!(cryptanalysis::column_sample_counts(&[]), vec![]);
+    }
+
+    #[test]
+    fn common_blocks() {
+        let message_a = [[0x11; 16], [0x22; 16], [0x33; 16]].concat();
+        let message_b = [[0x11; 16], [0x44; 16], [0x33; 16]].concat();
+        let keyed_cipher = aes::keyed_cipher_128(&KEY);
+        let ecb = |message| modes::ecb_encrypt_bytes_128u8(&keyed_cipher,
+                                                           message);
+        assert_eq!(cryptanalysis::common_blocks(&ecb(&message_a),
+                                                &ecb(&message_b)),
+                   3);
+        let cbc = |message| modes::cbc_encrypt_bytes_128u8(&keyed_cipher,
+                                                           PLAINTEXT,
+                                                           message);
+        assert_eq!(cryptanalysis::common_blocks(&cbc(&message_a),
+                                                &cbc(&message_b)),
+                   1);
+        assert_eq!(cryptanalysis::common_blocks(&message_a,
+                                                &message_a[..47]),
+                   0);
+    }
+
+    #[test]
+    fn guess_key_size() {
+        let mut message = b"Cryptography is the practice and study of \
+                            techniques for secure communication in the \
+                            presence of third parties called adversaries. \
+                            More generally, it is about constructing and \
+                            analyzing protocols that prevent third parties \
+                            or the public from reading private messages."
+                          .to_vec();
+        let key = [0x5a, 0xc3, 0x17].iter()
+                                    .cycle()
+                                    .cloned()
+                                    .take(message.len())
+                                    .collect::<Vec<u8>>();
+        inplace_xor_bytes(&mut message, &key);
+        assert_eq!(cryptanalysis::guess_key_size(&message, 2..9), 3);
+        assert_eq!(cryptanalysis::guess_key_size(&message, 0..9), 3);
+        assert_eq!(cryptanalysis::guess_key_size(&message[..5], 3..9), 3);
+
+        // In this message, 6-byte blocks score slightly better than 3-byte
+        // ones, but not by enough for the larger key size to be picked
+        let distance = |key_size| {
+            cryptanalysis::key_size_distance(&message, key_size).unwrap()
+        };
+        assert!(distance(6) < distance(3));
+        assert!(distance(6)
+                > distance(3) * cryptanalysis::KEY_SIZE_IMPROVEMENT_THRESHOLD);
+        assert_eq!(cryptanalysis::guess_key_size(&message, 3..7), 3);
+        assert_eq!(cryptanalysis::guess_key_size(&message, 6..7), 6);
+    }
+
+    #[test]
+    fn break_repeating_key_xor() {
+        let plaintext = b"Cryptography is the practice and study of \
+                          techniques for secure communication in the \
+                          presence of third parties called adversaries. \
+                          More generally, it is about constructing and \
+                          analyzing protocols that prevent third parties \
+                          or the public from reading private messages.";
+        let key = [0x5a, 0xc3, 0x17];
+        let mut ciphertext = plaintext.to_vec();
+        let key_stream = key.iter()
+                            .cycle()
+                            .cloned()
+                            .take(ciphertext.len())
+                            .collect::<Vec<u8>>();
+        inplace_xor_bytes(&mut ciphertext, &key_stream);
+        assert_eq!(cryptanalysis::break_repeating_key_xor(&ciphertext, 2..9),
+                   Some((key.to_vec(), plaintext.to_vec())));
+        assert_eq!(cryptanalysis::break_repeating_key_xor(&ciphertext[..5],
+                                                          3..9),
+                   None);
+    }
+}
@@ -6,11 +6,41 @@
 pub fn as_printable_char(byte: u8) -> char {
     match byte {
         // Can be interpreted as a printable ASCII character
-        b if b >= 0x20 && b <= 0x7E => b as char,
+        b if (0x20..=0x7E).contains(&b) => b as char,
         // Cannot be interpreted as printable ASCII
-        _ => '࿕',
+        _ => NON_PRINTABLE_CHAR,
     }
 }
+//
+const NON_PRINTABLE_CHAR: char = '࿕';
+
+
+// Compute the fraction of bytes which map to printable ASCII characters. This
+// is a cheap heuristic for ranking candidate plaintexts in brute-force attacks.
+// An empty message is considered to contain no printable characters.
+pub fn printable_ratio(bytes: &[u8]) -> f64 {
+    if bytes.is_empty() { return 0.0; }
+    let printable_count =
+        bytes.iter()
+             .filter(|&&byte| as_printable_char(byte) != NON_PRINTABLE_CHAR)
+             .count();
+    printable_count as f64 / bytes.len() as f64
+}
+
+
+// Strip leading and trailing ASCII whitespace (space, tab, CR and LF) from a
+// decrypted message. This works on raw bytes, so the message does not need to
+// be valid UTF-8.
+pub fn trim_ascii_whitespace(bytes: &[u8]) -> &[u8] {
+    let is_whitespace = |byte: &u8| b" \t\r\n".contains(byte);
+    let start = bytes.iter()
+                     .position(|byte| !is_whitespace(byte))
+                     .unwrap_or(bytes.len());
+    let end = bytes.iter()
+                   .rposition(|byte| !is_whitespace(byte))
+                   .map_or(start, |last| last + 1);
+    &bytes[start..end]
+}
 
 
 // Display a set of messages column-wise, both in numerical form and after
@@ -23,7 +53,7 @@ pub fn print_columns<P>(labels: &[String], messages: &[Vec<u8>], to_char: P)
 
     // Determine how many lines of output we will print. Being requested to
     // print zero messages is probably an error, so we'll panic in this case.
-    let output_len = ::max_length(&messages).unwrap();
+    let output_len = ::max_length(messages).unwrap();
 
     // Display the labels
     println!();
@@ -48,3 +78,64 @@ pub fn print_columns<P>(labels: &[String], messages: &[Vec<u8>], to_char: P)
     }
     println!();
 }
+
+
+// Display two messages side by side, both in numerical form and as printable
+// characters, marking the positions where they differ with a '*'. This is
+// useful when comparing an expected and an actual decryption.
+pub fn diff_columns(label_a: &str, a: &[u8],
+                    label_b: &str, b: &[u8]) -> String {
+    let mut result = format!("{}\t{}\n\n", label_a, label_b);
+    let output_len = a.len().max(b.len());
+    for line in 0..output_len {
+        let (byte_a, byte_b) = (a.get(line), b.get(line));
+        for byte in &[byte_a, byte_b] {
+            match *byte {
+                Some(&byte) => {
+                    let printable = as_printable_char(byte);
+                    result.push_str(&format!("{} {}", printable, byte));
+                },
+                None => result.push_str("   "),
+            }
+            result.push('\t');
+        }
+        if byte_a != byte_b { result.push('*'); }
+        result.push('\n');
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use display;
+
+    #[test]
+    fn trim_ascii_whitespace() {
+        assert_eq!(display::trim_ascii_whitespace(b"\t\r\n Hi"), b"Hi");
+        assert_eq!(display::trim_ascii_whitespace(b"Hi \r\n"), b"Hi");
+        assert_eq!(display::trim_ascii_whitespace(b" Hello, \tworld!\n"),
+                   b"Hello, \tworld!");
+        assert_eq!(display::trim_ascii_whitespace(b"\x00\xff "), b"\x00\xff");
+        assert_eq!(display::trim_ascii_whitespace(b" \n "), b"");
+        assert_eq!(display::trim_ascii_whitespace(b""), b"");
+    }
+
+    #[test]
+    fn printable_ratio() {
+        assert_eq!(display::printable_ratio(b"Hello, world!"), 1.0);
+        assert_eq!(display::printable_ratio(&[0x00, 0x01, 0x80, 0xff]), 0.0);
+        assert_eq!(display::printable_ratio(b"ab\x00\x01"), 0.5);
+    }
+
+    #[test]
+    fn diff_columns() {
+        let diff = display::diff_columns("expected", b"abc", "actual", b"aXcd");
+        assert_eq!(diff, ["expected\tactual\n",
+                          "\n",
+                          "a 97\ta 97\t\n",
+                          "b 98\tX 88\t*\n",
+                          "c 99\tc 99\t\n",
+                          "   \td 100\t*\n"].concat());
+    }
+}
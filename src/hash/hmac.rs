@@ -0,0 +1,90 @@
+//! This module is an implementation of the HMAC message authentication code,
+//! built on top of the SHA-256 hashing algorithm
+
+use ct_eq;
+use hash::sha_256::{self, Digest, Sha256};
+
+
+// HMAC works on keys which are as long as the block size of the hash function
+const BLOCK_LEN_U8: usize = 512/8;
+
+// The inner and outer keys are derived by XORing the key with these bytes
+const IPAD: u8 = 0x36;
+const OPAD: u8 = 0x5c;
+
+
+// Compute the HMAC-SHA256 authentication tag of a message
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> Digest {
+    // Keys which are longer than a block are hashed, then all keys are padded
+    // with zeroes up to the block size
+    let mut block_key = [0u8; BLOCK_LEN_U8];
+    if key.len() > BLOCK_LEN_U8 {
+        let key_digest = sha_256::sha_256(key);
+        block_key[..key_digest.len()].copy_from_slice(&key_digest);
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+
+    // Compute the inner hash, H((K ^ ipad) || message)...
+    let mut inner = Sha256::new();
+    inner.update(&block_key.iter().map(|b| b ^ IPAD).collect::<Vec<_>>());
+    inner.update(message);
+    let inner_digest = inner.finalize();
+
+    // ...then the outer hash, H((K ^ opad) || inner hash)
+    let mut outer = Sha256::new();
+    outer.update(&block_key.iter().map(|b| b ^ OPAD).collect::<Vec<_>>());
+    outer.update(&inner_digest);
+    outer.finalize()
+}
+
+
+// Check the HMAC-SHA256 authentication tag of a message. The comparison is
+// performed in constant time, and tags of the wrong length are rejected.
+pub fn verify_sha256(key: &[u8], message: &[u8], tag: &[u8]) -> bool {
+    ct_eq(&hmac_sha256(key, message), tag)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use hash::hmac;
+
+    // Test case 2 from RFC 4231
+    const KEY: &[u8] = b"Jefe";
+    const MESSAGE: &[u8] = b"what do ya want for nothing?";
+    const TAG: [u8; 32] = [0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e,
+                           0x6a, 0x04, 0x24, 0x26, 0x08, 0x95, 0x75, 0xc7,
+                           0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83,
+                           0x9d, 0xec, 0x58, 0xb9, 0x64, 0xec, 0x38, 0x43];
+
+    #[test]
+    fn hmac_sha256() {
+        assert_eq!(hmac::hmac_sha256(KEY, MESSAGE), TAG);
+    }
+
+    #[test]
+    fn long_key() {
+        // Test case 6 from RFC 4231
+        let key = [0xaa; 131];
+        let message = b"Test Using Larger Than Block-Size Key - Hash Key First";
+        assert_eq!(hmac::hmac_sha256(&key, message),
+                   [0x60, 0xe4, 0x31, 0x59, 0x1e, 0xe0, 0xb6, 0x7f,
+                    0x0d, 0x8a, 0x26, 0xaa, 0xcb, 0xf5, 0xb7, 0x7f,
+                    0x8e, 0x0b, 0xc6, 0x21, 0x37, 0x28, 0xc5, 0x14,
+                    0x05, 0x46, 0x04, 0x0f, 0x0e, 0xe3, 0x7f, 0x54]);
+    }
+
+    #[test]
+    fn verify_valid_tag() {
+        assert!(hmac::verify_sha256(KEY, MESSAGE, &TAG));
+    }
+
+    #[test]
+    fn verify_invalid_tag() {
+        let mut flipped_tag = TAG;
+        flipped_tag[7] ^= 0x01;
+        assert!(!hmac::verify_sha256(KEY, MESSAGE, &flipped_tag));
+        assert!(!hmac::verify_sha256(KEY, MESSAGE, &TAG[..31]));
+    }
+}
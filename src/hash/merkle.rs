@@ -0,0 +1,132 @@
+//! This module implements Merkle trees on top of SHA-256. A Merkle tree hashes
+//! a list of data blocks (leaves) into a single root digest, in such a way
+//! that the inclusion of a leaf can be proven with only a logarithmic amount
+//! of digests.
+
+use ct_eq;
+use hash::sha_256::{self, Digest};
+
+
+// Hash each leaf, then hash pairs of digests until a single one remains. As in
+// Bitcoin, the last digest of levels with an odd size is paired with itself.
+pub fn merkle_root(leaves: &[Vec<u8>]) -> Digest {
+    let mut level = leaf_digests(leaves);
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+
+// Build a proof that a leaf is part of a Merkle tree. It consists of the
+// digests which the leaf's digest is paired with, from the bottom of the tree
+// to the top.
+pub fn inclusion_proof(leaves: &[Vec<u8>], index: usize) -> Vec<Digest> {
+    assert!(index < leaves.len(), "Leaf index is out of range");
+    let mut proof = Vec::new();
+    let mut level = leaf_digests(leaves);
+    let mut index = index;
+    while level.len() > 1 {
+        let sibling = (index ^ 1).min(level.len() - 1);
+        proof.push(level[sibling]);
+        level = next_level(&level);
+        index /= 2;
+    }
+    proof
+}
+
+// Check that a leaf at a given index is part of the Merkle tree with the
+// specified root, using an inclusion proof
+pub fn verify_proof(leaf: &[u8],
+                    index: usize,
+                    proof: &[Digest],
+                    root: &Digest) -> bool {
+    let mut digest = sha_256::sha_256(leaf);
+    let mut index = index;
+    for sibling in proof {
+        digest = if index % 2 == 0 {
+            hash_pair(&digest, sibling)
+        } else {
+            hash_pair(sibling, &digest)
+        };
+        index /= 2;
+    }
+    index == 0 && ct_eq(&digest, root)
+}
+
+
+// Hash the leaves of the tree. There must be at least one leaf.
+fn leaf_digests(leaves: &[Vec<u8>]) -> Vec<Digest> {
+    assert!(!leaves.is_empty(), "A Merkle tree needs at least one leaf");
+    leaves.iter().map(|leaf| sha_256::sha_256(leaf)).collect()
+}
+
+// Compute the next level of the tree by hashing pairs of digests
+fn next_level(level: &[Digest]) -> Vec<Digest> {
+    level.chunks(2)
+         .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+         .collect()
+}
+
+// Hash a pair of digests into their parent digest
+fn hash_pair(left: &Digest, right: &Digest) -> Digest {
+    sha_256::sha_256_multi(&[left, right])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use hash::merkle;
+    use hash::sha_256::{sha_256, sha_256_multi};
+
+    fn leaves(count: usize) -> Vec<Vec<u8>> {
+        (0..count).map(|index| format!("leaf {}", index).into_bytes())
+                  .collect()
+    }
+
+    #[test]
+    fn single_leaf() {
+        let leaves = leaves(1);
+        let root = merkle::merkle_root(&leaves);
+        assert_eq!(root, sha_256(&leaves[0]));
+        let proof = merkle::inclusion_proof(&leaves, 0);
+        assert!(proof.is_empty());
+        assert!(merkle::verify_proof(&leaves[0], 0, &proof, &root));
+    }
+
+    #[test]
+    fn two_leaves() {
+        let leaves = leaves(2);
+        let (hash_0, hash_1) = (sha_256(&leaves[0]), sha_256(&leaves[1]));
+        let root = merkle::merkle_root(&leaves);
+        assert_eq!(root, sha_256_multi(&[&hash_0, &hash_1]));
+        assert_eq!(merkle::inclusion_proof(&leaves, 0), vec![hash_1]);
+        assert_eq!(merkle::inclusion_proof(&leaves, 1), vec![hash_0]);
+        for index in 0..2 {
+            let proof = merkle::inclusion_proof(&leaves, index);
+            assert!(merkle::verify_proof(&leaves[index], index, &proof, &root));
+            assert!(!merkle::verify_proof(&leaves[index],
+                                          1 - index,
+                                          &proof,
+                                          &root));
+        }
+    }
+
+    #[test]
+    fn three_leaves() {
+        let leaves = leaves(3);
+        let hashes = leaves.iter()
+                           .map(|leaf| sha_256(leaf))
+                           .collect::<Vec<_>>();
+        let left = sha_256_multi(&[&hashes[0], &hashes[1]]);
+        let right = sha_256_multi(&[&hashes[2], &hashes[2]]);
+        let root = merkle::merkle_root(&leaves);
+        assert_eq!(root, sha_256_multi(&[&left, &right]));
+        assert_eq!(merkle::inclusion_proof(&leaves, 2), vec![hashes[2], left]);
+        for index in 0..3 {
+            let proof = merkle::inclusion_proof(&leaves, index);
+            assert!(merkle::verify_proof(&leaves[index], index, &proof, &root));
+            assert!(!merkle::verify_proof(b"forged", index, &proof, &root));
+        }
+    }
+}
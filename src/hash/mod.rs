@@ -1,3 +1,30 @@
 //! This module contains implementations of cryptographic hash functions
 
+pub mod hmac;
+pub mod merkle;
+pub mod rfc6979;
+pub mod sha3_256;
 pub mod sha_256;
+
+use hexfile;
+
+
+// Hash functions output fixed-size arrays of bytes, which are most often
+// displayed in hexadecimal form
+pub fn to_hex_digest<const N: usize>(digest: &[u8; N]) -> String {
+    hexfile::to_hex(&digest[..])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use hash;
+    use hash::sha_256::sha_256;
+
+    #[test]
+    fn to_hex_digest() {
+        assert_eq!(hash::to_hex_digest(&sha_256(b"abc")),
+                   "ba7816bf8f01cfea414140de5dae2223\
+                    b00361a396177a9cb410ff61f20015ad");
+    }
+}
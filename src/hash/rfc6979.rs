@@ -0,0 +1,114 @@
+//! This module implements the deterministic nonce generation procedure from
+//! RFC 6979, using HMAC-SHA256 as the underlying HMAC_DRBG primitive.
+//!
+//! The RFC requires the nonce k to be reduced into the range [1, q-1], where q
+//! is the order of the group of the signature scheme. Since this module only
+//! knows the bit length of q, it does not reduce the message hash modulo q and
+//! only rejects the k = 0 candidate. This matches the RFC whenever the message
+//! hash and the first candidate are smaller than q, which is very likely for
+//! common curves like P-256.
+
+use hash::hmac::hmac_sha256;
+use hash::sha_256::Digest;
+
+
+// Generate a deterministic nonce of q_bits bits, returned as a big-endian
+// integer of ceil(q_bits/8) bytes, from a secret key and a message hash
+pub fn generate_nonce(secret: &[u8],
+                      message_hash: &Digest,
+                      q_bits: usize) -> Vec<u8> {
+    assert!(q_bits > 0);
+    let r_len = (q_bits + 7) / 8;
+
+    // Encode the secret key and the message hash as integers of r_len bytes
+    let x = int2octets(secret, r_len);
+    let h_1 = int2octets(&bits2int(message_hash, q_bits), r_len);
+
+    // Initialize the HMAC_DRBG state
+    let mut v = [0x01; 32];
+    let mut k = [0x00; 32];
+    let hmac_k = |k: &Digest, parts: &[&[u8]]| hmac_sha256(k, &parts.concat());
+    k = hmac_k(&k, &[&v, &[0x00], &x, &h_1]);
+    v = hmac_k(&k, &[&v]);
+    k = hmac_k(&k, &[&v, &[0x01], &x, &h_1]);
+    v = hmac_k(&k, &[&v]);
+
+    // Generate nonce candidates until a nonzero one is found
+    loop {
+        let mut t = Vec::with_capacity(r_len + v.len());
+        while t.len() < r_len {
+            v = hmac_k(&k, &[&v]);
+            t.extend_from_slice(&v);
+        }
+        let candidate = int2octets(&bits2int(&t, q_bits), r_len);
+        if candidate.iter().any(|&byte| byte != 0) {
+            return candidate;
+        }
+        k = hmac_k(&k, &[&v, &[0x00]]);
+        v = hmac_k(&k, &[&v]);
+    }
+}
+
+
+// Interpret the leftmost q_bits bits of a bit string as a big-endian integer
+fn bits2int(bytes: &[u8], q_bits: usize) -> Vec<u8> {
+    // Keep the leftmost bytes which contain the q_bits first bits
+    let kept_len = ((q_bits + 7) / 8).min(bytes.len());
+    let mut result = bytes[..kept_len].to_vec();
+
+    // Shift the result to the right if too many bits were kept
+    let excess_bits = (8 * kept_len).saturating_sub(q_bits);
+    if excess_bits > 0 {
+        let mut carry = 0;
+        for byte in result.iter_mut() {
+            let new_carry = *byte << (8 - excess_bits);
+            *byte = (*byte >> excess_bits) | carry;
+            carry = new_carry;
+        }
+    }
+    result
+}
+
+// Encode a big-endian integer on exactly r_len bytes
+fn int2octets(int: &[u8], r_len: usize) -> Vec<u8> {
+    if int.len() >= r_len {
+        int[int.len()-r_len..].to_vec()
+    } else {
+        let mut result = vec![0; r_len - int.len()];
+        result.extend_from_slice(int);
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use hash::rfc6979;
+    use hash::sha_256::sha_256;
+    use hexfile;
+
+    // ECDSA P-256 / SHA-256 "sample" example from RFC 6979 appendix A.2.5
+    #[test]
+    fn p_256_sample() {
+        let secret = hexfile::parse_hex("c9afa9d845ba75166b5c215767b1d693\
+                                         4e50c3db36e89b127b8a622b120f6721")
+                                       .unwrap();
+        let message_hash = sha_256(b"sample");
+        let nonce = rfc6979::generate_nonce(&secret, &message_hash, 256);
+        assert_eq!(hexfile::to_hex(&nonce),
+                   "a6e3c57dd01abe90086538398355dd4c\
+                    3b17aa873382b0f24d6129493d8aad60");
+
+        // The nonce is deterministic
+        assert_eq!(rfc6979::generate_nonce(&secret, &message_hash, 256),
+                   nonce);
+    }
+
+    // Nonces have the requested bit length
+    #[test]
+    fn odd_bit_length() {
+        let nonce = rfc6979::generate_nonce(b"secret", &sha_256(b"msg"), 163);
+        assert_eq!(nonce.len(), 21);
+        assert_eq!(nonce[0] >> 3, 0);
+    }
+}
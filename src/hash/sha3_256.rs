@@ -0,0 +1,140 @@
+//! This module is an implementation of the SHA3-256 hashing algorithm. Unlike
+//! SHA-256, which is based on the Merkle-Damgård construction, SHA-3 is based
+//! on the sponge construction: message blocks are XORed ("absorbed") into a
+//! part of a large state, which is scrambled by a fixed permutation
+//! (Keccak-f[1600]) after each block, and the digest is then read
+//! ("squeezed") out of the state.
+
+
+// The Keccak-f[1600] state is a 5x5 array of 64-bit lanes, where lane (x, y)
+// is stored at index x + 5*y
+type KeccakState = [u64; 25];
+
+// Round constants of the iota step
+const RC: [u64; 24] = [0x0000000000000001, 0x0000000000008082,
+                       0x800000000000808a, 0x8000000080008000,
+                       0x000000000000808b, 0x0000000080000001,
+                       0x8000000080008081, 0x8000000000008009,
+                       0x000000000000008a, 0x0000000000000088,
+                       0x0000000080008009, 0x000000008000000a,
+                       0x000000008000808b, 0x800000000000008b,
+                       0x8000000000008089, 0x8000000000008003,
+                       0x8000000000008002, 0x8000000000000080,
+                       0x000000000000800a, 0x800000008000000a,
+                       0x8000000080008081, 0x8000000000008080,
+                       0x0000000080000001, 0x8000000080008008];
+
+// The rho and pi steps move lanes around the state while rotating them. These
+// are the successive lane indices visited, and the matching rotation amounts.
+const PI_LANES: [usize; 24] = [10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4,
+                               15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1];
+const RHO_OFFSETS: [u32; 24] = [1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14,
+                                27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44];
+
+
+// The Keccak-f[1600] permutation
+fn keccak_f1600(state: &mut KeccakState) {
+    for rc in RC.iter() {
+        // Theta: XOR each lane with the parities of two neighbouring columns
+        let mut parities = [0u64; 5];
+        for (x, parity) in parities.iter_mut().enumerate() {
+            *parity = state[x] ^ state[x+5] ^ state[x+10]
+                               ^ state[x+15] ^ state[x+20];
+        }
+        for x in 0..5 {
+            let theta = parities[(x+4) % 5]
+                        ^ parities[(x+1) % 5].rotate_left(1);
+            for y in 0..5 {
+                state[x + 5*y] ^= theta;
+            }
+        }
+
+        // Rho and pi: rotate the lanes and move them to new positions
+        let mut carried_lane = state[1];
+        for (&lane, &offset) in PI_LANES.iter().zip(RHO_OFFSETS.iter()) {
+            let next_lane = state[lane];
+            state[lane] = carried_lane.rotate_left(offset);
+            carried_lane = next_lane;
+        }
+
+        // Chi: non-linear mixing of the lanes of each row
+        for y in 0..5 {
+            let row = [state[5*y], state[5*y+1], state[5*y+2],
+                       state[5*y+3], state[5*y+4]];
+            for x in 0..5 {
+                state[x + 5*y] = row[x] ^ (!row[(x+1) % 5] & row[(x+2) % 5]);
+            }
+        }
+
+        // Iota: break the symmetry with a round constant
+        state[0] ^= rc;
+    }
+}
+
+
+// SHA3-256 digests will be emitted in the following format
+pub const DIGEST_LEN: usize = 256/8;
+pub type Digest = [u8; DIGEST_LEN];
+
+// The rate of the sponge is the part of the state which message blocks are
+// absorbed into. For SHA3-256, it is 1600 - 2*256 bits.
+const RATE_U8: usize = (1600 - 2*256)/8;
+
+
+// Compute the SHA3-256 hash of any message
+pub fn sha3_256(message: &[u8]) -> Digest {
+    // Pad the message with the SHA-3 domain separation bits and the sponge's
+    // 10*1 padding, which yields a 0x06 ... 0x80 byte pattern
+    let mut padded_message = message.to_vec();
+    padded_message.push(0x06);
+    while padded_message.len() % RATE_U8 != 0 {
+        padded_message.push(0x00);
+    }
+    *padded_message.last_mut().unwrap() |= 0x80;
+
+    // Absorb the padded message, one block at a time. Bytes are mapped to
+    // lanes in little-endian order.
+    let mut state = [0u64; 25];
+    for block in padded_message.chunks(RATE_U8) {
+        for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+            *lane ^= u64::from_le_bytes(*array_ref!(bytes, 0, 8));
+        }
+        keccak_f1600(&mut state);
+    }
+
+    // Squeeze the digest, which fits in the first block of the output
+    let mut result = [0u8; DIGEST_LEN];
+    for (bytes, lane) in result.chunks_mut(8).zip(state.iter()) {
+        bytes.copy_from_slice(&lane.to_le_bytes());
+    }
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use hash::sha3_256::sha3_256;
+    use hexfile;
+
+    #[test]
+    fn empty_message() {
+        assert_eq!(hexfile::to_hex(&sha3_256(b"")),
+                   "a7ffc6f8bf1ed76651c14756a061d662\
+                    f580ff4de43b49fa82d80a4b80f8434a");
+    }
+
+    #[test]
+    fn abc() {
+        assert_eq!(hexfile::to_hex(&sha3_256(b"abc")),
+                   "3a985da74fe225b2045c172d6bd390bd\
+                    855f086e3e9d525b46bfe24511431532");
+    }
+
+    #[test]
+    fn two_block_message() {
+        let input = [0xa3; 200];
+        assert_eq!(hexfile::to_hex(&sha3_256(&input)),
+                   "79f38adec5c20307a98ef76e8324afbf\
+                    d46cfd81b22e3973c65fa1bd9de31787");
+    }
+}
@@ -1,7 +1,13 @@
 //! This module is an implementation of the SHA-256 hashing algorithm
 
+use blocks::{Block512u32, BLOCK_LEN_512_U32};
+use ct_eq;
+use hexfile;
 use padding::PaddingScheme;
 use padding::merkle_damgard::MDPadding512u32;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
 
 
 // Logical functions used by SHA-256 (function names taken from NIST standard)
@@ -67,49 +73,317 @@ pub fn sha_256(message: &[u8]) -> Digest {
     // Parse and pad the message into 512-bit blocks of 32-bit words, then
     // iterate over the resulting message blocks
     for message_block in MDPadding512u32::new(message) {
-        // Prepare the message schedule
-        let mut w = [0; 64];
-        w[0..16].copy_from_slice(&message_block[..]);
-        for t in 16..64 {
-            w[t] = sigma_1(w[t-2]).wrapping_add(w[t-7])
-                                  .wrapping_add(sigma_0(w[t-15]))
-                                  .wrapping_add(w[t-16]);
+        compress(&mut hash, &message_block);
+    }
+
+    // Output the final hash value
+    into_digest(&hash)
+}
+
+// Faster variant of sha_256, which produces identical digests but uses the
+// optimized compression function below
+pub fn sha_256_fast(message: &[u8]) -> Digest {
+    let mut hash = H_0;
+    for message_block in MDPadding512u32::new(message) {
+        compress_fast(&mut hash, &message_block);
+    }
+    into_digest(&hash)
+}
+
+
+// Raw digests are plain arrays of bytes, which do not display nicely. This
+// newtype displays as lowercase hex, can be parsed from hex, and is compared in
+// constant time so that it may safely be used to check MACs.
+#[derive(Clone, Copy, Debug)]
+pub struct Sha256Digest(pub Digest);
+//
+impl PartialEq for Sha256Digest {
+    fn eq(&self, other: &Self) -> bool {
+        ct_eq(&self.0, &other.0)
+    }
+}
+//
+impl Eq for Sha256Digest {}
+//
+impl fmt::Display for Sha256Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hexfile::to_hex(&self.0))
+    }
+}
+//
+impl FromStr for Sha256Digest {
+    type Err = DigestParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hexfile::parse_hex(s).map_err(DigestParseError::Hex)?;
+        if bytes.len() != DIGEST_LEN {
+            return Err(DigestParseError::WrongLength(bytes.len()));
         }
+        let mut digest = [0; DIGEST_LEN];
+        digest.copy_from_slice(&bytes);
+        Ok(Sha256Digest(digest))
+    }
+}
 
-        // Initialize the eight working variables from the previous hash value
-        let (mut a, mut b, mut c, mut d) = (hash[0], hash[1], hash[2], hash[3]);
-        let (mut e, mut f, mut g, mut h) = (hash[4], hash[5], hash[6], hash[7]);
-
-        // Compute the hash increment
-        for t in 0..64 {
-            let t_1 = h.wrapping_add(capital_sigma_1(e))
-                       .wrapping_add(ch(e, f, g))
-                       .wrapping_add(K[t])
-                       .wrapping_add(w[t]);
-            let t_2 = capital_sigma_0(a).wrapping_add(maj(a, b, c));
-            h = g;
-            g = f;
-            f = e;
-            e = d.wrapping_add(t_1);
-            d = c;
-            c = b;
-            b = a;
-            a = t_1.wrapping_add(t_2);
+/// Possible errors when parsing a SHA-256 digest from hex
+#[derive(Debug)]
+pub enum DigestParseError {
+    /// The input is not valid hexadecimal data
+    Hex(hexfile::Error),
+
+    /// The input does not decode to 32 bytes (the decoded length is included)
+    WrongLength(usize),
+}
+
+// Compute the SHA-256 hash of any message, as a Sha256Digest
+pub fn sha_256_typed(message: &[u8]) -> Sha256Digest {
+    Sha256Digest(sha_256(message))
+}
+
+
+// The hash computation may also be started from an arbitrary chaining value
+// instead of H_0, e.g. to resume a hash or to build tree hashes. In this case,
+// the amount of message bytes which were processed to produce the chaining
+// value must be provided, so that the final padding uses the full length.
+// Since chaining values are only available at block boundaries, this amount
+// should be a multiple of the 512-bit block size.
+pub fn sha_256_from(iv: Digest, message: &[u8], total_prior_bytes: u64)
+    -> Digest
+{
+    debug_assert_eq!(total_prior_bytes % (BLOCK_LEN_512_U8 as u64), 0);
+    let mut hasher = Sha256 {
+        hash: from_digest(&iv),
+        buffer: [0; BLOCK_LEN_512_U8],
+        buffer_len: 0,
+        bytes_processed: total_prior_bytes,
+    };
+    hasher.update(message);
+    hasher.finalize()
+}
+
+
+// SHA-256 may also be computed incrementally, when the message is not
+// available all at once (e.g. when it comes from a stream). In this case,
+// message bytes are buffered until a full 512-bit block is available.
+const BLOCK_LEN_512_U8: usize = 512/8;
+//
+pub struct Sha256 {
+    // Current hash value
+    hash: [u32; 8],
+
+    // Message bytes which do not fill a complete block yet
+    buffer: [u8; BLOCK_LEN_512_U8],
+    buffer_len: usize,
+
+    // Total amount of message bytes processed so far
+    bytes_processed: u64,
+}
+//
+impl Sha256 {
+    // Start hashing a new message
+    pub fn new() -> Self {
+        Self {
+            hash: H_0,
+            buffer: [0; BLOCK_LEN_512_U8],
+            buffer_len: 0,
+            bytes_processed: 0,
         }
+    }
 
-        // Update the hash value
-        hash[0] = hash[0].wrapping_add(a);
-        hash[1] = hash[1].wrapping_add(b);
-        hash[2] = hash[2].wrapping_add(c);
-        hash[3] = hash[3].wrapping_add(d);
-        hash[4] = hash[4].wrapping_add(e);
-        hash[5] = hash[5].wrapping_add(f);
-        hash[6] = hash[6].wrapping_add(g);
-        hash[7] = hash[7].wrapping_add(h);
+    // Feed the next bytes of the message into the hash
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        self.bytes_processed += bytes.len() as u64;
+        while !bytes.is_empty() {
+            // Fill the block buffer as much as possible
+            let buffer_room = BLOCK_LEN_512_U8 - self.buffer_len;
+            let copy_len = buffer_room.min(bytes.len());
+            let buffer_end = self.buffer_len + copy_len;
+            self.buffer[self.buffer_len..buffer_end]
+                .copy_from_slice(&bytes[..copy_len]);
+            self.buffer_len = buffer_end;
+            bytes = &bytes[copy_len..];
+
+            // Hash the block buffer once it is full
+            if self.buffer_len == BLOCK_LEN_512_U8 {
+                let block = into_block(&self.buffer);
+                compress(&mut self.hash, &block);
+                self.buffer_len = 0;
+            }
+        }
     }
 
-    // Output the final hash value
-    let mut result = [0u8; 256/8];
+    // Go back to the initial state, discarding all bytes fed so far, so that
+    // the hasher can be reused for another message
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    // Tell how many message bytes have been fed into the hash so far. This is
+    // the message length that will be used by the final padding.
+    pub fn bytes_processed(&self) -> u64 {
+        self.bytes_processed
+    }
+
+    // Apply Merkle-Damgård padding and output the final hash value
+    pub fn finalize(mut self) -> Digest {
+        let message_bits = self.bytes_processed * 8;
+        self.update(&[0x80]);
+        while self.buffer_len != BLOCK_LEN_512_U8 - 8 {
+            self.update(&[0]);
+        }
+        self.update(&message_bits.to_be_bytes());
+        debug_assert_eq!(self.buffer_len, 0);
+        into_digest(&self.hash)
+    }
+}
+//
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Compute the SHA-256 hash of the concatenation of several byte slices, without
+// copying them into a combined buffer (e.g. to hash key || message in MACs)
+pub fn sha_256_multi(parts: &[&[u8]]) -> Digest {
+    sha_256_iter(parts)
+}
+
+// More generally, the parts may come from any iterator of byte containers,
+// such as a Vec<Vec<u8>> or a lazily assembled sequence of messages
+pub fn sha_256_iter<I, B>(parts: I) -> Digest
+    where I: IntoIterator<Item=B>,
+          B: AsRef<[u8]>
+{
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_ref());
+    }
+    hasher.finalize()
+}
+
+
+// Update the hash value with one block of (padded) message
+fn compress(hash: &mut [u32; 8], message_block: &Block512u32) {
+    // Prepare the message schedule
+    let mut w = [0; 64];
+    w[0..16].copy_from_slice(&message_block[..]);
+    for t in 16..64 {
+        w[t] = sigma_1(w[t-2]).wrapping_add(w[t-7])
+                              .wrapping_add(sigma_0(w[t-15]))
+                              .wrapping_add(w[t-16]);
+    }
+
+    // Initialize the eight working variables from the previous hash value
+    let (mut a, mut b, mut c, mut d) = (hash[0], hash[1], hash[2], hash[3]);
+    let (mut e, mut f, mut g, mut h) = (hash[4], hash[5], hash[6], hash[7]);
+
+    // Compute the hash increment
+    for t in 0..64 {
+        let t_1 = h.wrapping_add(capital_sigma_1(e))
+                   .wrapping_add(ch(e, f, g))
+                   .wrapping_add(K[t])
+                   .wrapping_add(w[t]);
+        let t_2 = capital_sigma_0(a).wrapping_add(maj(a, b, c));
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t_1);
+        d = c;
+        c = b;
+        b = a;
+        a = t_1.wrapping_add(t_2);
+    }
+
+    // Update the hash value
+    hash[0] = hash[0].wrapping_add(a);
+    hash[1] = hash[1].wrapping_add(b);
+    hash[2] = hash[2].wrapping_add(c);
+    hash[3] = hash[3].wrapping_add(d);
+    hash[4] = hash[4].wrapping_add(e);
+    hash[5] = hash[5].wrapping_add(f);
+    hash[6] = hash[6].wrapping_add(g);
+    hash[7] = hash[7].wrapping_add(h);
+}
+
+// Optimized version of the compression function. The message schedule is kept
+// in a rolling window of 16 words, each new word overwriting the one which was
+// computed 16 rounds before. Rounds are unrolled by groups of 8, which allows
+// the working variables to be renamed instead of shifted at every round.
+fn compress_fast(hash: &mut [u32; 8], message_block: &Block512u32) {
+    // Compute a round, updating d and h (the rest is done by renaming)
+    macro_rules! round {
+        ($a:ident, $b:ident, $c:ident, $d:ident,
+         $e:ident, $f:ident, $g:ident, $h:ident, $w:expr, $t:expr) => {
+            let t_1 = $h.wrapping_add(capital_sigma_1($e))
+                        .wrapping_add(ch($e, $f, $g))
+                        .wrapping_add(K[$t])
+                        .wrapping_add($w);
+            let t_2 = capital_sigma_0($a).wrapping_add(maj($a, $b, $c));
+            $d = $d.wrapping_add(t_1);
+            $h = t_1.wrapping_add(t_2);
+        }
+    }
+
+    // Compute the next word of the message schedule, in place
+    let mut w = *message_block;
+    let mut schedule = |t: usize| -> u32 {
+        if t >= 16 {
+            w[t % 16] = sigma_1(w[(t-2) % 16])
+                            .wrapping_add(w[(t-7) % 16])
+                            .wrapping_add(sigma_0(w[(t-15) % 16]))
+                            .wrapping_add(w[t % 16]);
+        }
+        w[t % 16]
+    };
+
+    // Run the rounds by groups of 8
+    let (mut a, mut b, mut c, mut d) = (hash[0], hash[1], hash[2], hash[3]);
+    let (mut e, mut f, mut g, mut h) = (hash[4], hash[5], hash[6], hash[7]);
+    for t in (0..64).step_by(8) {
+        round!(a, b, c, d, e, f, g, h, schedule(t), t);
+        round!(h, a, b, c, d, e, f, g, schedule(t+1), t+1);
+        round!(g, h, a, b, c, d, e, f, schedule(t+2), t+2);
+        round!(f, g, h, a, b, c, d, e, schedule(t+3), t+3);
+        round!(e, f, g, h, a, b, c, d, schedule(t+4), t+4);
+        round!(d, e, f, g, h, a, b, c, schedule(t+5), t+5);
+        round!(c, d, e, f, g, h, a, b, schedule(t+6), t+6);
+        round!(b, c, d, e, f, g, h, a, schedule(t+7), t+7);
+    }
+
+    // Update the hash value
+    hash[0] = hash[0].wrapping_add(a);
+    hash[1] = hash[1].wrapping_add(b);
+    hash[2] = hash[2].wrapping_add(c);
+    hash[3] = hash[3].wrapping_add(d);
+    hash[4] = hash[4].wrapping_add(e);
+    hash[5] = hash[5].wrapping_add(f);
+    hash[6] = hash[6].wrapping_add(g);
+    hash[7] = hash[7].wrapping_add(h);
+}
+
+// Turn a block of bytes into a block of big-endian 32-bit words
+fn into_block(bytes: &[u8; BLOCK_LEN_512_U8]) -> Block512u32 {
+    debug_assert_eq!(BLOCK_LEN_512_U8, mem::size_of::<Block512u32>());
+    let mut result = [0u32; BLOCK_LEN_512_U32];
+    for (input, output) in bytes.chunks(4).zip(result.iter_mut()) {
+        *output = u32::from_be_bytes([input[0], input[1], input[2], input[3]]);
+    }
+    result
+}
+
+// Turn a digest back into a hash value
+fn from_digest(digest: &Digest) -> [u32; 8] {
+    let mut result = [0u32; 8];
+    for (input, output) in digest.chunks(4).zip(result.iter_mut()) {
+        *output = u32::from_be_bytes([input[0], input[1], input[2], input[3]]);
+    }
+    result
+}
+
+// Turn the final hash value into a digest
+fn into_digest(hash: &[u32; 8]) -> Digest {
+    let mut result = [0u8; DIGEST_LEN];
     for (input, outputs) in hash.iter().zip(result.chunks_mut(4)) {
         outputs.copy_from_slice(&[(*input >> 24) as u8,
                                   ((*input >> 16) & 0xff) as u8,
@@ -122,7 +396,8 @@ pub fn sha_256(message: &[u8]) -> Digest {
 
 #[cfg(test)]
 mod tests {
-    use hash::sha_256::sha_256;
+    use hash::sha_256::{self, sha_256, DigestParseError, Sha256, Sha256Digest};
+    use std::time::Instant;
 
     #[test]
     fn one_block_message_sample() {
@@ -250,6 +525,102 @@ mod tests {
                           0xb0, 0xb6, 0x92, 0xb9, 0x24, 0xcc, 0x80, 0x25]);
     }
 
+    // Check that the fast implementation agrees with the reference one on the
+    // inputs of all the test vectors above
+    #[test]
+    fn fast() {
+        let two_blocks = b"abcdbcdecdefdefgefghfghighijhijk\
+                           ijkljklmklmnlmnomnopnopq";
+        let inputs: Vec<&[u8]> = vec![b"abc", two_blocks, &[0xbd],
+                                      &[0xc9, 0x8c, 0x8e, 0x55], &[0; 55],
+                                      &[0; 56], &[0; 57], &[0; 64], &[0; 1000],
+                                      &[0x41; 1000], &[0x55; 1005]];
+        for input in inputs {
+            assert_eq!(sha_256::sha_256_fast(input), sha_256(input));
+        }
+        let input = vec![0; 1_000_000];
+        assert_eq!(sha_256::sha_256_fast(&input), sha_256(&input));
+    }
+
+    #[test]
+    fn multi() {
+        assert_eq!(sha_256::sha_256_multi(&[b"ab", b"c"]), sha_256(b"abc"));
+        assert_eq!(sha_256::sha_256_multi(&[]), sha_256(b""));
+    }
+
+    #[test]
+    fn typed_digest() {
+        const ABC_HEX: &str = "ba7816bf8f01cfea414140de5dae2223\
+                               b00361a396177a9cb410ff61f20015ad";
+        let digest = sha_256::sha_256_typed(b"abc");
+        assert_eq!(digest.0, sha_256(b"abc"));
+        assert_eq!(digest.to_string(), ABC_HEX);
+        assert_eq!(ABC_HEX.parse::<Sha256Digest>().unwrap(), digest);
+        assert_eq!(ABC_HEX.to_uppercase().parse::<Sha256Digest>().unwrap(),
+                   digest);
+        assert_ne!(sha_256::sha_256_typed(b"abd"), digest);
+        match ABC_HEX[..62].parse::<Sha256Digest>() {
+            Err(DigestParseError::WrongLength(31)) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match ABC_HEX[..63].parse::<Sha256Digest>() {
+            Err(DigestParseError::Hex(_)) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn iter() {
+        let parts = vec![b"Hello".to_vec(), vec![], b", world!".to_vec()];
+        assert_eq!(sha_256::sha_256_iter(&parts), sha_256(b"Hello, world!"));
+        assert_eq!(sha_256::sha_256_iter(parts.iter().rev()),
+                   sha_256(b", world!Hello"));
+        assert_eq!(sha_256::sha_256_iter(Vec::<Vec<u8>>::new()), sha_256(b""));
+    }
+
+    #[test]
+    fn incremental() {
+        let input = (0..1000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut hasher = Sha256::new();
+        assert_eq!(hasher.bytes_processed(), 0);
+        hasher.update(&input[..3]);
+        hasher.update(&input[3..3]);
+        hasher.update(&input[3..130]);
+        assert_eq!(hasher.bytes_processed(), 130);
+        hasher.update(&input[130..]);
+        assert_eq!(hasher.bytes_processed(), 1000);
+        assert_eq!(hasher.finalize(), sha_256(&input));
+    }
+
+    #[test]
+    fn reset() {
+        let mut hasher = Sha256::new();
+        hasher.update(&[0x42; 100]);
+        hasher.reset();
+        assert_eq!(hasher.bytes_processed(), 0);
+        hasher.update(b"abc");
+        let mut fresh = Sha256::new();
+        fresh.update(b"abc");
+        assert_eq!(hasher.finalize(), fresh.finalize());
+    }
+
+    #[test]
+    fn custom_initial_value() {
+        // Starting from H_0 without prior bytes is the same as plain SHA-256
+        let input = (0..200).map(|i| i as u8).collect::<Vec<_>>();
+        let h_0 = sha_256::into_digest(&sha_256::H_0);
+        assert_eq!(sha_256::sha_256_from(h_0, &input, 0), sha_256(&input));
+
+        // Chaining from the hash value after one block works as well
+        let mut hash = sha_256::H_0;
+        sha_256::compress(&mut hash, &sha_256::into_block(
+            array_ref!(input, 0, sha_256::BLOCK_LEN_512_U8)
+        ));
+        let chaining_value = sha_256::into_digest(&hash);
+        assert_eq!(sha_256::sha_256_from(chaining_value, &input[64..], 64),
+                   sha_256(&input));
+    }
+
     // The following tests are highly ressource intensive and should only be
     // run in release mode, which is why they are ignored by default.
     #[test]
@@ -284,4 +655,20 @@ mod tests {
                           0x62, 0xa2, 0x20, 0x04, 0x5a, 0x03, 0xeb, 0x2d,
                           0xfe, 0xd4, 0x8e, 0xf9, 0xb0, 0x5a, 0xab, 0xea]);
     }
+
+    // Compare the speed of the reference and fast implementations. This is
+    // only meaningful on a release build.
+    #[test]
+    #[ignore]
+    fn fast_benchmark() {
+        let input = vec![0x42; 64 * 1024 * 1024];
+        let start = Instant::now();
+        let reference = sha_256(&input);
+        let reference_time = start.elapsed();
+        let start = Instant::now();
+        let fast = sha_256::sha_256_fast(&input);
+        let fast_time = start.elapsed();
+        assert_eq!(fast, reference);
+        println!("Reference: {:?}, fast: {:?}", reference_time, fast_time);
+    }
 }
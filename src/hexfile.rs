@@ -5,7 +5,7 @@
 //! such ciphertext from a file, into a more convenient array of bytes.
 
 use std::fs::File;
-use std::io::{self, Read};
+use std::io::{self, BufRead, BufReader, Read};
 use std::result::Result;
 
 
@@ -20,26 +20,60 @@ pub enum Error {
     OddLength,
 
     /// The file contains characters which are not valid hexadecimal digits
-    InvalidChars
+    InvalidChars,
+
+    /// A decimal byte list contains a token which is not a number from 0 to
+    /// 255 (the offending token is included)
+    InvalidDecimal(String),
+
+    /// A line of a multi-message file could not be parsed (lines are numbered
+    /// from 1, as in text editors)
+    AtLine(usize, Box<Error>),
 }
 
 
 /// Load hex-encoded bytes from a file
 pub fn load_bytes(filename: &str) -> Result<Vec<u8>, Error> {
-    // Fetch string data from a file, and strip any trailing newline
+    let input_file = File::open(filename).map_err(Error::Loading)?;
+    load_bytes_from(input_file)
+}
+
+/// Load hex-encoded bytes from the standard input, so that this crate's
+/// tooling may be used in a pipe
+pub fn load_bytes_stdin() -> Result<Vec<u8>, Error> {
+    load_bytes_from(io::stdin())
+}
+
+/// Load hex-encoded bytes from any source of bytes
+pub fn load_bytes_from<R: Read>(mut input: R) -> Result<Vec<u8>, Error> {
+    // Fetch string data from the input, and strip any surrounding whitespace
     let mut raw_str = String::new();
-    {
-        let mut input_file = File::open(filename).map_err(Error::Loading)?;
-        input_file.read_to_string(&mut raw_str).map_err(Error::Loading)?;
-    }
-    let trimmed_str = raw_str.trim_right();
+    input.read_to_string(&mut raw_str).map_err(Error::Loading)?;
+    let trimmed_str = raw_str.trim();
 
     // Parse the result as a hex string
-    parse_hex(&trimmed_str)
+    parse_hex(trimmed_str)
+}
+
+
+/// Load a set of hex-encoded messages from a file, one message per line.
+/// Blank lines are skipped.
+pub fn load_many(filename: &str) -> Result<Vec<Vec<u8>>, Error> {
+    let input_file = File::open(filename).map_err(Error::Loading)?;
+    let mut messages = Vec::new();
+    for (index, line) in BufReader::new(input_file).lines().enumerate() {
+        let at_line = |error| Error::AtLine(index + 1, Box::new(error));
+        let line = line.map_err(|e| at_line(Error::Loading(e)))?;
+        let trimmed_line = line.trim();
+        if trimmed_line.is_empty() { continue; }
+        messages.push(parse_hex(trimmed_line).map_err(at_line)?);
+    }
+    Ok(messages)
 }
 
 
-// Parse a string of hex-encoded bytes
+// Parse a string of hex-encoded bytes. Both lowercase and uppercase digits
+// are accepted, and may be mixed.
 pub fn parse_hex(string: &str) -> Result<Vec<u8>, Error> {
     // Check that the string has a plausible length
     if string.len() % 2 != 0 { return Err(Error::OddLength); }
@@ -48,26 +82,142 @@ pub fn parse_hex(string: &str) -> Result<Vec<u8>, Error> {
     let mut chars = string.chars();
     let mut bytes = Vec::new();
     while let (Some(ch1), Some(ch2)) = (chars.next(), chars.next()) {
-        let digit1 = ch1.to_digit(16).ok_or(Error::InvalidChars)?;
-        let digit2 = ch2.to_digit(16).ok_or(Error::InvalidChars)?;
-        bytes.push((digit1 * 16 + digit2) as u8);
+        let digit1 = hex_digit(ch1).ok_or(Error::InvalidChars)?;
+        let digit2 = hex_digit(ch2).ok_or(Error::InvalidChars)?;
+        bytes.push(digit1 * 16 + digit2);
     }
 
     // Return the bytes
     Ok(bytes)
 }
 
+// Decode a single hexadecimal digit
+fn hex_digit(ch: char) -> Option<u8> {
+    match ch {
+        '0'..='9' => Some(ch as u8 - b'0'),
+        'a'..='f' => Some(ch as u8 - b'a' + 10),
+        'A'..='F' => Some(ch as u8 - b'A' + 10),
+        _ => None,
+    }
+}
+
 
-// Convert a sequence of bytes to a string
+// Parse a list of bytes written as decimal numbers, separated by whitespace
+// and/or commas (e.g. "1, 2, 255")
+pub fn parse_decimal_bytes(string: &str) -> Result<Vec<u8>, Error> {
+    string.split(|ch: char| ch.is_whitespace() || ch == ',')
+          .filter(|token| !token.is_empty())
+          .map(|token| {
+              token.parse::<u8>()
+                   .map_err(|_| Error::InvalidDecimal(token.to_owned()))
+          })
+          .collect()
+}
+
+
+// Convert a sequence of bytes to a lowercase hex string
 pub fn to_hex(bytes: &[u8]) -> String {
-    const HEX_DIGITS: &'static [char] = &['0', '1', '2', '3',
-                                          '4', '5', '6', '7',
-                                          '8', '9', 'a', 'b',
-                                          'c', 'd', 'e', 'f'];
+    to_hex_cased(bytes, HexCase::Lower)
+}
+
+/// Letter case of the hexadecimal digits emitted by to_hex_cased
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HexCase {
+    /// Digits above 9 are emitted as a-f
+    Lower,
+
+    /// Digits above 9 are emitted as A-F
+    Upper,
+}
+
+// Convert a sequence of bytes to a hex string of the requested case
+pub fn to_hex_cased(bytes: &[u8], case: HexCase) -> String {
+    const LOWER_HEX_DIGITS: &[char] = &['0', '1', '2', '3',
+                                        '4', '5', '6', '7',
+                                        '8', '9', 'a', 'b',
+                                        'c', 'd', 'e', 'f'];
+    const UPPER_HEX_DIGITS: &[char] = &['0', '1', '2', '3',
+                                        '4', '5', '6', '7',
+                                        '8', '9', 'A', 'B',
+                                        'C', 'D', 'E', 'F'];
+    let hex_digits = match case {
+        HexCase::Lower => LOWER_HEX_DIGITS,
+        HexCase::Upper => UPPER_HEX_DIGITS,
+    };
     let mut result = String::with_capacity(2 * bytes.len());
     for b in bytes {
-        result.push(HEX_DIGITS[(b >> 4) as usize]);
-        result.push(HEX_DIGITS[(b & 0xf) as usize]);
+        result.push(hex_digits[(b >> 4) as usize]);
+        result.push(hex_digits[(b & 0xf) as usize]);
     }
     result
 }
+
+
+#[cfg(test)]
+mod tests {
+    use hexfile::{self, Error, HexCase};
+    use test_utils::TempFile;
+
+    #[test]
+    fn hex_case() {
+        let bytes = hexfile::parse_hex("00AbCDef9F").unwrap();
+        assert_eq!(bytes, vec![0x00, 0xab, 0xcd, 0xef, 0x9f]);
+        assert_eq!(hexfile::to_hex(&bytes), "00abcdef9f");
+        assert_eq!(hexfile::to_hex_cased(&bytes, HexCase::Lower), "00abcdef9f");
+        assert_eq!(hexfile::to_hex_cased(&bytes, HexCase::Upper), "00ABCDEF9F");
+        let upper = hexfile::to_hex_cased(&bytes, HexCase::Upper);
+        assert_eq!(hexfile::parse_hex(&upper).unwrap(), bytes);
+        match hexfile::parse_hex("0G") {
+            Err(Error::InvalidChars) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_decimal_bytes() {
+        assert_eq!(hexfile::parse_decimal_bytes("1, 2, 255").unwrap(),
+                   vec![1, 2, 255]);
+        assert_eq!(hexfile::parse_decimal_bytes(" 0\t42\n7,8 ").unwrap(),
+                   vec![0, 42, 7, 8]);
+        assert!(hexfile::parse_decimal_bytes("").unwrap().is_empty());
+        match hexfile::parse_decimal_bytes("1, 256") {
+            Err(Error::InvalidDecimal(ref token)) if token == "256" => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match hexfile::parse_decimal_bytes("1, x") {
+            Err(Error::InvalidDecimal(ref token)) if token == "x" => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_bytes_from() {
+        let input = &b"  0102ff\r\n"[..];
+        assert_eq!(hexfile::load_bytes_from(input).unwrap(),
+                   vec![0x01, 0x02, 0xff]);
+        match hexfile::load_bytes_from(&b"010\n"[..]) {
+            Err(Error::OddLength) => {},
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn load_many() {
+        let file = TempFile::new(b"0102ff\n\nabcd\n");
+        let messages = hexfile::load_many(file.path()).unwrap();
+        assert_eq!(messages, vec![vec![0x01, 0x02, 0xff], vec![0xab, 0xcd]]);
+    }
+
+    #[test]
+    fn load_many_bad_line() {
+        let file = TempFile::new(b"0102\n\nabc\n");
+        let result = hexfile::load_many(file.path());
+        match result {
+            Err(Error::AtLine(3, ref error)) => match **error {
+                Error::OddLength => {},
+                ref other => panic!("Unexpected inner error {:?}", other),
+            },
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
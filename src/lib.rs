@@ -9,11 +9,15 @@ extern crate arrayref;
 
 pub mod blocks;
 pub mod block_ciphers;
+pub mod cryptanalysis;
 pub mod display;
 pub mod hash;
 pub mod hexfile;
 pub mod padding;
 
+#[cfg(test)]
+mod test_utils;
+
 
 // Compute the maximum length of a set of messages, if non-empty
 pub fn max_length(messages: &[Vec<u8>]) -> Option<usize> {
@@ -32,6 +36,45 @@ pub fn xor_bytes(bytes1: &[u8], bytes2: &[u8]) -> Vec<u8> {
 }
 
 
+// Count the number of bits which differ between two messages. As in
+// xor_bytes, only the shortest subset of the two messages is considered.
+pub fn hamming_distance(bytes1: &[u8], bytes2: &[u8]) -> u32 {
+    bytes1.iter().zip(bytes2.iter())
+                 .map(|(b1, b2)| (b1 ^ b2).count_ones())
+                 .sum()
+}
+
+
+// Compare two messages for equality in a time which only depends on their
+// length, so that the position of the first mismatch is not leaked
+pub fn ct_eq(bytes1: &[u8], bytes2: &[u8]) -> bool {
+    if bytes1.len() != bytes2.len() { return false; }
+    let difference = bytes1.iter().zip(bytes2.iter())
+                                  .fold(0, |acc, (b1, b2)| acc | (b1 ^ b2));
+    difference == 0
+}
+
+
+// Perform an in-place XOR of a message with a keystream, processing 16 bytes at
+// a time using 128-bit integer operations, then the remaining tail bytewise.
+// As with inplace_xor_bytes, the keystream must be at least as long as dst.
+pub(crate) fn xor_bulk(dst: &mut [u8], key: &[u8]) {
+    assert!(dst.len() <= key.len());
+    const WORD_LEN: usize = 128/8;
+    let bulk_len = dst.len() - dst.len() % WORD_LEN;
+    let (dst_bulk, dst_tail) = dst.split_at_mut(bulk_len);
+    for (dst_word, key_word) in dst_bulk.chunks_exact_mut(WORD_LEN)
+                                        .zip(key.chunks_exact(WORD_LEN)) {
+        let dst_int = u128::from_ne_bytes(*array_ref!(dst_word, 0, WORD_LEN));
+        let key_int = u128::from_ne_bytes(*array_ref!(key_word, 0, WORD_LEN));
+        dst_word.copy_from_slice(&(dst_int ^ key_int).to_ne_bytes());
+    }
+    for (acc, byte) in dst_tail.iter_mut().zip(key[bulk_len..].iter()) {
+        *acc ^= *byte;
+    }
+}
+
+
 // Perform an in-place XOR, i.e. XOR the bytes from the first slice with those
 // of the second slice and store the result in the first slice. Unlike in
 // xor_bytes, if the second message is shorter, the function will need to abort,
@@ -42,3 +85,21 @@ pub fn inplace_xor_bytes(accumulator: &mut [u8], operand: &[u8]) {
         *acc ^= *byte;
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use {xor_bulk, xor_bytes};
+
+    // Check that bulk XOR matches bytewise XOR for all kinds of lengths
+    #[test]
+    fn xor_bulk_matches_xor_bytes() {
+        let key = (0..100).map(|i| (i * 37 + 11) as u8).collect::<Vec<_>>();
+        for len in 0..70 {
+            let message = (0..len).map(|i| (i * 13) as u8).collect::<Vec<_>>();
+            let mut actual = message.clone();
+            xor_bulk(&mut actual, &key[..len + (len % 3)]);
+            assert_eq!(actual, xor_bytes(&message, &key));
+        }
+    }
+}
@@ -7,8 +7,10 @@
 
 use blocks::{Block512u32, BLOCK_LEN_512_U32};
 use padding::PaddingScheme;
+use std::io::{self, ErrorKind, Read};
 use std::mem;
 use std::slice::Chunks;
+use std::vec;
 
 
 // Due to current Rust limitations on genericity over array types, only 512-bit
@@ -33,7 +35,7 @@ impl<'a> Iterator for MDPadding512u32<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.raw_iterator.next() {
             // Input bytes are forwarded to the output as words, with padding
-            Some(ref input_slice) => {
+            Some(input_slice) => {
                 // Check input slice size and prepare output block
                 let input_len = input_slice.len();
                 let mut result = [0u32; BLOCK_LEN_512_U32];
@@ -52,7 +54,7 @@ impl<'a> Iterator for MDPadding512u32<'a> {
                     // Start with a '1' bit, which comes after the last byte
                     let word_index = input_len / 4;
                     let word_shift = (3 - (input_len % 4)) * 8;
-                    result[word_index] |= 1 << 7+word_shift;
+                    result[word_index] |= 1 << (7+word_shift);
                     self.final_bit_sent = true;
 
                     // Add message length in bits if there is enough room
@@ -138,11 +140,110 @@ impl<'a> MDPadding512u32<'a> {
 }
 
 
+// When the message is too large to fit in memory, it can instead be padded as
+// it is read from a stream. The message length is then only known at the end
+// of the stream, so the final padded block(s) are only produced at that point.
+// Since reading may fail, this iterator produces io::Results.
+pub struct MDPaddingReader512u32<R: Read> {
+    // Source of message bytes
+    reader: R,
+
+    // Amount of message bytes read so far
+    message_len: u64,
+
+    // Final padded blocks, available once the end of the stream is reached
+    tail: Option<vec::IntoIter<Block512u32>>,
+}
+//
+impl<R: Read> MDPaddingReader512u32<R> {
+    // Start padding the message from a reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            message_len: 0,
+            tail: None,
+        }
+    }
+
+    // Read as many bytes as possible into a buffer, stopping early only at
+    // the end of the stream, and tell how many bytes were read
+    fn read_bytes(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut read_len = 0;
+        while read_len < buffer.len() {
+            match self.reader.read(&mut buffer[read_len..]) {
+                Ok(0) => break,
+                Ok(len) => read_len += len,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(read_len)
+    }
+}
+//
+impl<R: Read> Iterator for MDPaddingReader512u32<R> {
+    type Item = io::Result<Block512u32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Once the end of the stream is reached, emit the final blocks
+        if let Some(ref mut tail) = self.tail {
+            return tail.next().map(Ok);
+        }
+
+        // Otherwise, try to read a full block of message bytes. Reading stops
+        // on errors, which are reported to the client.
+        let block_size_u8 = mem::size_of::<Block512u32>();
+        let mut buffer = vec![0u8; 2 * block_size_u8];
+        let read_len = match self.read_bytes(&mut buffer[..block_size_u8]) {
+            Ok(read_len) => read_len,
+            Err(e) => {
+                self.tail = Some(Vec::new().into_iter());
+                return Some(Err(e));
+            }
+        };
+        self.message_len += read_len as u64;
+        if read_len == block_size_u8 {
+            return Some(Ok(bytes_to_block(&buffer[..block_size_u8])));
+        }
+
+        // At the end of the stream, append the '1' bit and the message length,
+        // which may require an extra block if there is not enough room left
+        buffer[read_len] = 0x80;
+        let tail_len = if read_len + 1 + 64/8 <= block_size_u8 {
+            block_size_u8
+        } else {
+            2 * block_size_u8
+        };
+        let message_bits = self.message_len * 8;
+        buffer[tail_len-8..tail_len]
+            .copy_from_slice(&message_bits.to_be_bytes());
+        let mut tail = buffer[..tail_len].chunks(block_size_u8)
+                                         .map(bytes_to_block)
+                                         .collect::<Vec<_>>()
+                                         .into_iter();
+        let first_block = tail.next();
+        self.tail = Some(tail);
+        first_block.map(Ok)
+    }
+}
+
+// Turn a block of bytes into a block of big-endian 32-bit words
+fn bytes_to_block(bytes: &[u8]) -> Block512u32 {
+    let mut result = [0u32; BLOCK_LEN_512_U32];
+    for (output, input) in result.iter_mut().zip(bytes.chunks(4)) {
+        *output = u32::from_be_bytes(*array_ref!(input, 0, 4));
+    }
+    result
+}
+
+
+// Expected blocks separate the padding byte from message bytes in literals
 #[cfg(test)]
+#[allow(clippy::unusual_byte_groupings)]
 mod tests {
     use blocks::Block512u32;
     use padding::PaddingScheme;
-    use padding::merkle_damgard::MDPadding512u32;
+    use padding::merkle_damgard::{MDPadding512u32, MDPaddingReader512u32};
     use std::mem;
 
     #[test]
@@ -258,4 +359,16 @@ mod tests {
                                              0, 0, 0, 0, 0, 0, 0, 512]));
         assert_eq!(padded_iter.next(), None);
     }
+
+    #[test]
+    fn reader_matches_slice() {
+        for len in 0..200 {
+            let input = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+            let expected = MDPadding512u32::new(&input).collect::<Vec<_>>();
+            let actual = MDPaddingReader512u32::new(&input[..])
+                                              .collect::<Result<Vec<_>, _>>()
+                                              .unwrap();
+            assert_eq!(actual, expected);
+        }
+    }
 }
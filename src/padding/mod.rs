@@ -1,9 +1,15 @@
 //! This module implements padding schemes for turning arbitrary slices of bytes
 //! into streams of fixed-size blocks.
+//!
+//! It is the only padding implementation of this crate: the block cipher modes
+//! of operation consume its PaddingScheme trait and its PKCS#7 implementation
+//! directly, and hash functions use its Merkle-Damgård implementation.
 
 pub mod merkle_damgard;
 pub mod pkcs7;
 
+use blocks::{Block128u8, BLOCK_LEN_128_U8};
+
 
 // A padding scheme starts from a message (represented as a slice of bytes) and
 // produces a stream of fixed-size blocks (= arrays of unsigned numbers).
@@ -17,3 +23,64 @@ pub trait PaddingScheme<'a, Block> : Iterator<Item=Block> {
     // Padded output is produced from an input message (slice of bytes)
     fn new(bytes: &'a [u8]) -> Self;
 }
+
+
+// When reverse-engineering an unknown ciphertext, it is useful to know which
+// common padding schemes would accept the final decrypted block as validly
+// padded. Only PKCS#7 is implemented by this crate, the other schemes are
+// merely recognized. Several schemes may match the same block.
+pub fn guess_scheme(last_block: &Block128u8) -> Vec<&'static str> {
+    let mut schemes = Vec::new();
+    if pkcs7::is_valid_padding(last_block) {
+        schemes.push("PKCS#7");
+    }
+    if is_ansi_x923_padding(last_block) {
+        schemes.push("ANSI X.923");
+    }
+    if is_iso_7816_4_padding(last_block) {
+        schemes.push("ISO/IEC 7816-4");
+    }
+    if last_block[BLOCK_LEN_128_U8 - 1] == 0 {
+        schemes.push("zero");
+    }
+    schemes
+}
+
+// ANSI X.923 padding is made of zeroes followed by the padding length
+fn is_ansi_x923_padding(block: &Block128u8) -> bool {
+    let padding_len = block[BLOCK_LEN_128_U8 - 1] as usize;
+    if padding_len == 0 || padding_len > BLOCK_LEN_128_U8 {
+        return false;
+    }
+    let padding = &block[BLOCK_LEN_128_U8 - padding_len..];
+    padding[..padding_len - 1].iter().all(|&byte| byte == 0)
+}
+
+// ISO/IEC 7816-4 padding is made of a 0x80 byte followed by zeroes
+fn is_iso_7816_4_padding(block: &Block128u8) -> bool {
+    block.iter().rev().find(|&&byte| byte != 0) == Some(&0x80)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use padding;
+
+    #[test]
+    fn guess_scheme() {
+        let mut block = [0x42; 16];
+        assert_eq!(padding::guess_scheme(&block), Vec::<&str>::new());
+
+        block[15] = 0x01;
+        assert_eq!(padding::guess_scheme(&block), vec!["PKCS#7", "ANSI X.923"]);
+
+        block[13..].copy_from_slice(&[0x00, 0x00, 0x03]);
+        assert_eq!(padding::guess_scheme(&block), vec!["ANSI X.923"]);
+
+        block[13..].copy_from_slice(&[0x80, 0x00, 0x00]);
+        assert_eq!(padding::guess_scheme(&block),
+                   vec!["ISO/IEC 7816-4", "zero"]);
+
+        assert_eq!(padding::guess_scheme(&[0x10; 16]), vec!["PKCS#7"]);
+    }
+}
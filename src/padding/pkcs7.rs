@@ -23,7 +23,7 @@ impl<'a> Iterator for PKCS7Padding128u8<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         match self.raw_iterator.next() {
             // Input slices are forwarded to the output, possibly with padding
-            Some(ref input_slice) => {
+            Some(input_slice) => {
                 // Copy all bytes from the input slice to the output block
                 let input_len = input_slice.len();
                 let mut result = [0; BLOCK_LEN_128_U8];
@@ -69,16 +69,99 @@ impl<'a> PaddingScheme<'a, Block128u8> for PKCS7Padding128u8<'a> {
         Self {
             raw_iterator: bytes.chunks(block_size_u8),
             final_block_sent: false,
-            block_count: bytes.len()/block_size_u8 + 1,
+            block_count: padded_block_count(bytes.len()),
         }
     }
 }
 
 
+// Compute how many blocks the padding of a message of a given length produces,
+// including the extra padding block which block-aligned messages receive
+pub fn padded_block_count(input_len: usize) -> usize {
+    input_len / BLOCK_LEN_128_U8 + 1
+}
+
+// Compute the length in bytes of a padded message, e.g. for preallocation
+pub fn padded_byte_len(input_len: usize) -> usize {
+    padded_block_count(input_len) * BLOCK_LEN_128_U8
+}
+
+// Pad a message into a vector of blocks, for callers which prefer to have all
+// padded blocks available at once rather than iterating over them
+pub fn pad_to_blocks(bytes: &[u8]) -> Vec<Block128u8> {
+    PKCS7Padding128u8::new(bytes).collect()
+}
+
+
+// PKCS#7-style padding may also be applied for other multiples than the block
+// size: between 1 and `multiple` bytes are appended, each equal to the amount
+// of padding bytes, so that a message which is already on a boundary receives
+// a full extra multiple of padding. Since the padding length must fit in a
+// byte, the multiple is limited to 255 bytes (so e.g. 256-byte boundaries
+// cannot be reached with this scheme), and this function will panic if given
+// a larger multiple or a zero multiple.
+pub fn pad_to_multiple(bytes: &[u8], multiple: usize) -> Vec<u8> {
+    assert!(multiple > 0 && multiple <= 255,
+            "PKCS#7-style padding requires a multiple between 1 and 255");
+    let pad_len = multiple - bytes.len() % multiple;
+    let mut result = Vec::with_capacity(bytes.len() + pad_len);
+    result.extend_from_slice(bytes);
+    result.resize(bytes.len() + pad_len, pad_len as u8);
+    result
+}
+
+
+/// Possible errors when trying to remove PKCS#7 padding
+#[derive(Debug, Eq, PartialEq)]
+pub enum PadError {
+    /// The input is empty, and thus cannot contain any padding
+    Empty,
+
+    /// The final bytes of the input are not valid PKCS#7 padding
+    Invalid,
+}
+
+// Remove the PKCS#7 padding from a vector of bytes by truncating it in place,
+// after checking that the padding is valid.
+pub fn unpad_vec(data: &mut Vec<u8>) -> Result<(), PadError> {
+    let message_len = unpadded_len(data)?;
+    data.truncate(message_len);
+    Ok(())
+}
+
+// Check that some bytes carry valid PKCS#7 padding, and compute the length of
+// the message without the padding
+pub fn unpadded_len(data: &[u8]) -> Result<usize, PadError> {
+    // Read the amount of padding bytes from the last byte
+    let padding_len = match data.last() {
+        Some(&last_byte) => last_byte as usize,
+        None => return Err(PadError::Empty),
+    };
+
+    // Check that it is a valid amount, and that all padding bytes agree
+    if padding_len == 0 || padding_len > BLOCK_LEN_128_U8
+                        || padding_len > data.len() {
+        return Err(PadError::Invalid);
+    }
+    let message_len = data.len() - padding_len;
+    if data[message_len..].iter().any(|&b| b as usize != padding_len) {
+        return Err(PadError::Invalid);
+    }
+    Ok(message_len)
+}
+
+// Tell whether some bytes end with valid PKCS#7 padding. This is the predicate
+// which a padding oracle leaks to an attacker.
+pub fn is_valid_padding(data: &[u8]) -> bool {
+    unpadded_len(data).is_ok()
+}
+
+
 #[cfg(test)]
 mod tests {
+    use blocks;
     use padding::PaddingScheme;
-    use padding::pkcs7::PKCS7Padding128u8;
+    use padding::pkcs7::{self, PadError, PKCS7Padding128u8};
 
     #[test]
     fn empty_input() {
@@ -119,4 +202,110 @@ mod tests {
                                              16, 16, 16, 16, 16, 16, 16, 16]));
         assert_eq!(padded_iter.next(), None);
     }
+
+    #[test]
+    fn pad_to_blocks() {
+        for len in 0..40 {
+            let input = vec![42; len];
+            let expected_count = PKCS7Padding128u8::new(&input).size_hint().0;
+            let blocks = pkcs7::pad_to_blocks(&input);
+            assert_eq!(blocks.len(), expected_count);
+            assert_eq!(blocks,
+                       PKCS7Padding128u8::new(&input).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn padded_len() {
+        for &(input_len, blocks) in [(0, 1), (15, 1), (16, 2), (17, 2)].iter() {
+            assert_eq!(pkcs7::padded_block_count(input_len), blocks);
+            assert_eq!(pkcs7::padded_byte_len(input_len), blocks * 16);
+            assert_eq!(pkcs7::pad_to_blocks(&vec![0; input_len]).len(),
+                       blocks);
+        }
+    }
+
+    #[test]
+    fn pad_to_multiple() {
+        // With 16-byte multiples, this is regular PKCS#7 padding
+        for len in [0, 5, 16, 17].iter().cloned() {
+            let input = vec![42; len];
+            assert_eq!(pkcs7::pad_to_multiple(&input, 16),
+                       blocks::into_vec_128u8(
+                           PKCS7Padding128u8::new(&input)
+                       ));
+        }
+
+        // Other multiples work the same way
+        let padded = pkcs7::pad_to_multiple(&[42; 20], 32);
+        assert_eq!(padded.len(), 32);
+        assert_eq!(&padded[..20], &[42; 20][..]);
+        assert_eq!(&padded[20..], &[12; 12][..]);
+
+        // Messages already on a boundary get a full multiple of padding
+        let padded = pkcs7::pad_to_multiple(&[42; 64], 32);
+        assert_eq!(padded.len(), 96);
+        assert_eq!(&padded[64..], &[32; 32][..]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn pad_to_multiple_too_large() {
+        pkcs7::pad_to_multiple(&[42; 20], 256);
+    }
+
+    #[test]
+    fn unpadded_len() {
+        let data = [42, 43, 44, 45, 46, 47, 48, 49,
+                    50, 51, 52, 53, 54, 4, 4, 4];
+        assert_eq!(pkcs7::unpadded_len(&data[..]), Err(PadError::Invalid));
+        assert_eq!(pkcs7::unpadded_len(&[42, 43, 44, 45, 46, 47, 48, 49,
+                                         50, 51, 52, 53, 4, 4, 4, 4]),
+                   Ok(12));
+        assert_eq!(pkcs7::unpadded_len(&[42, 43, 44, 45, 46, 47, 48, 49,
+                                         50, 51, 52, 53, 54, 55, 56, 0]),
+                   Err(PadError::Invalid));
+        assert_eq!(pkcs7::unpadded_len(&[]), Err(PadError::Empty));
+    }
+
+    #[test]
+    fn is_valid_padding() {
+        assert!(pkcs7::is_valid_padding(&[42, 43, 44, 45, 46, 47, 48, 49,
+                                          50, 51, 52, 53, 54, 3, 3, 3]));
+        assert!(!pkcs7::is_valid_padding(&[0; 16]));
+        assert!(pkcs7::is_valid_padding(&[0x10; 16]));
+        assert!(!pkcs7::is_valid_padding(&[0x11; 17]));
+        assert!(!pkcs7::is_valid_padding(&[]));
+    }
+
+    #[test]
+    fn unpad_vec_valid() {
+        let mut data = vec![42, 43, 44, 13, 13, 13, 13, 13,
+                            13, 13, 13, 13, 13, 13, 13, 13];
+        assert_eq!(pkcs7::unpad_vec(&mut data), Ok(()));
+        assert_eq!(data, vec![42, 43, 44]);
+    }
+
+    #[test]
+    fn unpad_vec_full_block() {
+        let mut data = vec![16; 16];
+        assert_eq!(pkcs7::unpad_vec(&mut data), Ok(()));
+        assert_eq!(data, vec![]);
+    }
+
+    #[test]
+    fn unpad_vec_invalid() {
+        let mut empty = Vec::new();
+        assert_eq!(pkcs7::unpad_vec(&mut empty), Err(PadError::Empty));
+        let mut zero_padding = vec![42, 0];
+        assert_eq!(pkcs7::unpad_vec(&mut zero_padding),
+                   Err(PadError::Invalid));
+        let mut inconsistent_padding = vec![42, 43, 3, 2, 3];
+        assert_eq!(pkcs7::unpad_vec(&mut inconsistent_padding),
+                   Err(PadError::Invalid));
+        let mut too_much_padding = vec![17; 17];
+        assert_eq!(pkcs7::unpad_vec(&mut too_much_padding),
+                   Err(PadError::Invalid));
+        assert_eq!(too_much_padding, vec![17; 17]);
+    }
 }
@@ -0,0 +1,39 @@
+//! Helpers shared by the tests of several modules
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+// Temporary file, which is given a unique name so that concurrent test runs do
+// not collide, and which is deleted when dropped, even if the test panics
+pub struct TempFile {
+    path: PathBuf,
+}
+//
+impl TempFile {
+    // Create a temporary file with some contents
+    pub fn new(contents: &[u8]) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("coursera_crypto_{}_{}.tmp",
+                           process::id(),
+                           COUNTER.fetch_add(1, Ordering::Relaxed));
+        let path = env::temp_dir().join(name);
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        Self { path }
+    }
+
+    // Tell where the temporary file is, in the form expected by file loaders
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+}
+//
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}